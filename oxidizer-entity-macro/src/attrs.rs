@@ -12,6 +12,13 @@ pub struct RelationAttr {
     pub key: String,
 }
 
+impl RelationAttr {
+    /// Whether the relation points back to the entity declaring it (`model = "Self"`)
+    pub fn is_self_referential(&self) -> bool {
+        self.model == "Self"
+    }
+}
+
 #[derive(Debug, FromMeta, Clone)]
 pub struct IndexAttr {
     pub name: String,
@@ -22,7 +29,16 @@ pub struct IndexAttr {
 
 #[derive(Debug, FromMeta, Clone)]
 pub struct EntityAttr {
+    #[darling(default)]
     pub table_name: Option<String>,
+    #[darling(default)]
+    pub hooks: bool,
+    #[darling(default)]
+    pub emit_changes: bool,
+    #[darling(default)]
+    pub audited: bool,
+    #[darling(default)]
+    pub temporal: bool,
 }
 
 #[derive(Debug, FromMeta, Clone)]
@@ -33,7 +49,61 @@ pub struct HasManyAttr {
     pub through: Option<String>,
 }
 
+#[derive(Debug, FromMeta, Clone)]
+pub struct PolymorphicAttr {
+    pub name: String,
+    pub models: String,
+    #[darling(default)]
+    pub enum_name: Option<String>,
+}
+
+impl PolymorphicAttr {
+    pub fn get_models(&self) -> Vec<String> {
+        self.models
+            .split(',')
+            .map(|m| m.trim().to_string())
+            .filter(|m| !m.is_empty())
+            .collect()
+    }
+
+    pub fn get_type_field_name(&self) -> String {
+        format!("{}_type", self.name)
+    }
+
+    pub fn get_id_field_name(&self) -> String {
+        format!("{}_id", self.name)
+    }
+}
+
 #[derive(Debug, FromMeta)]
 pub struct CustomTypeAttr {
     pub ty: String,
 }
+
+#[derive(Debug, FromMeta, Default)]
+pub struct LengthAttr {
+    #[darling(default)]
+    pub min: Option<usize>,
+    #[darling(default)]
+    pub max: Option<usize>,
+}
+
+#[derive(Debug, FromMeta)]
+pub struct RangeAttr {
+    #[darling(default)]
+    pub min: Option<syn::Lit>,
+    #[darling(default)]
+    pub max: Option<syn::Lit>,
+}
+
+#[derive(Debug, FromMeta)]
+pub struct ValidateAttr {
+    #[darling(default)]
+    pub length: Option<LengthAttr>,
+    #[darling(default)]
+    pub email: bool,
+    #[darling(default)]
+    pub range: Option<RangeAttr>,
+    #[darling(default)]
+    pub custom: Option<String>,
+}
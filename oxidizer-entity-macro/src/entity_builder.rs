@@ -1,15 +1,18 @@
 use darling::FromMeta;
+use inflector::cases::pascalcase::to_pascal_case;
 use inflector::cases::snakecase::to_snake_case;
 use proc_macro::TokenStream;
 use proc_macro2::TokenStream as TokenStream2;
 use quote::{format_ident, quote, quote_spanned};
 use syn::{parse_macro_input, spanned::Spanned, DeriveInput, Type};
 
-use super::attrs::HasManyAttr;
 use super::attrs::{EntityAttr, IndexAttr};
+use super::attrs::{HasManyAttr, PolymorphicAttr};
 use super::field_extras::*;
+use super::from_row_builder::FromRowBuilder;
 use super::props::*;
 use super::sql_builder::{Builder, DefaultBuilder};
+use super::utils::option_inner_type;
 
 pub struct EntityBuilder {}
 
@@ -18,213 +21,1412 @@ impl EntityBuilder {
         EntityBuilder {}
     }
 
-    fn build_save_fn(&self, props: &Props) -> TokenStream2 {
-        let query = DefaultBuilder::build_save_query(props);
-
-        let fields_value_acessors: Vec<TokenStream2> = props
+    /// Builds the parameter value of every column of `receiver`, in the order of `get_fields_all`.
+    /// Values flagged as owned are converted from the field and have to be stored before being borrowed.
+    fn build_fields_values(
+        &self,
+        props: &Props,
+        receiver: &TokenStream2,
+    ) -> Vec<(TokenStream2, bool)> {
+        props
             .get_fields_all()
             .map(|field| {
                 let name = &field.ident;
                 if let Some(ct) = field.parse_custom_type() {
                     let ty = ct.ty;
 
-                    let ty_ident = format_ident!("{}", ty);
+                    let ty_ident = format_ident!("{}", ty);
+
+                    return (quote! { <#ty_ident>::try_from(&#receiver.#name)? }, true);
+                }
+
+                if field.parse_primary_key().is_some() && field.is_increments() {
+                    let ty = &field.ty;
+                    return (
+                        quote! {
+                            match #receiver.#name { v if v == <#ty>::default() => None, _ => Some(#receiver.#name) }
+                        },
+                        true,
+                    );
+                }
+
+                (quote! { #receiver.#name }, false)
+            })
+            .collect()
+    }
+
+    /// Builds the parameter accessors of every column, in the order of `get_fields_all`
+    fn build_fields_value_accessors(&self, props: &Props) -> Vec<TokenStream2> {
+        self.build_fields_values(props, &quote! { self })
+            .into_iter()
+            .map(|(value, _)| quote! { &#value })
+            .collect()
+    }
+
+    fn build_save_fn(&self, props: &Props) -> TokenStream2 {
+        let query = DefaultBuilder::build_save_query(props);
+
+        let fields_value_acessors = self.build_fields_value_accessors(props);
+
+        let primary_key = props.get_primary_key_field().unwrap();
+        let primary_key_ident = &primary_key.ident;
+        let primary_key_type = &primary_key.ty;
+
+        let returning_read = self.build_returning_read(props, &quote! { self });
+        let version_stale = self.build_version_stale(props);
+
+        let (snapshot_update, snapshot_capture) = match props.get_snapshot_field() {
+            Some(field) => (
+                self.build_save_changed(props, field),
+                self.build_snapshot_capture(field),
+            ),
+            None => (quote! {}, quote! {}),
+        };
+
+        let body = quote! {
+            self.validate()?;
+
+            let mut creating = false;
+            let primary_key_default: #primary_key_type = Default::default();
+
+            if self.#primary_key_ident == primary_key_default {
+                creating = true;
+            }
+
+            #snapshot_update
+
+            #query;
+            let rows = db.query(
+                query,
+                &[#( #fields_value_acessors ),*]
+            ).await?;
+            if let Some(first_row) = rows.first()  {
+                self.#primary_key_ident = first_row.get::<&str, #primary_key_type>(stringify!(#primary_key_ident));
+                #returning_read
+            } else if creating {
+               return Err(oxidizer::db::Error::Other("Error while saving entity".to_string()));
+            } else {
+                #version_stale
+            }
+
+            #snapshot_capture
+
+            Ok(creating)
+        };
+        let body = match props.get_snapshot_field() {
+            Some(_) if props.has_hooks() => {
+                let body = self.build_hooks_wrapper(
+                    props,
+                    "before_save",
+                    "after_save",
+                    quote! { bool },
+                    body,
+                    Some(quote! { unchanged }),
+                );
+                quote! {
+                    let mut unchanged = false;
+                    #body
+                }
+            }
+            _ => self.build_hooks_wrapper(
+                props,
+                "before_save",
+                "after_save",
+                quote! { bool },
+                body,
+                None,
+            ),
+        };
+
+        quote! {
+            async fn save(&mut self, db: &oxidizer::db::DB) -> oxidizer::db::DBResult<bool> {
+                #body
+            }
+        }
+    }
+
+    /// Wraps the body of a persistence method with the `before`/`after` hooks of entities opting
+    /// into `#[entity(hooks)]`. The `after` hook only runs when the body succeeded, and `skip_after`
+    /// is false when given.
+    fn build_hooks_wrapper(
+        &self,
+        props: &Props,
+        before: &str,
+        after: &str,
+        ret: TokenStream2,
+        body: TokenStream2,
+        skip_after: Option<TokenStream2>,
+    ) -> TokenStream2 {
+        if !props.has_hooks() {
+            return body;
+        }
+
+        let before = format_ident!("{}", before);
+        let after = format_ident!("{}", after);
+        let after = match skip_after {
+            Some(skip_after) => quote! {
+                if !#skip_after {
+                    oxidizer::hooks::EntityHooks::#after(self, db).await?;
+                }
+            },
+            None => quote! {
+                oxidizer::hooks::EntityHooks::#after(self, db).await?;
+            },
+        };
+
+        quote! {
+            oxidizer::hooks::EntityHooks::#before(self, db).await?;
+
+            let result: oxidizer::db::DBResult<#ret> = async { #body }.await;
+            let result = result?;
+
+            #after
+            Ok(result)
+        }
+    }
+
+    /// Builds the expression decoding `row`, running the `after_load` hook when enabled
+    fn build_load_row(&self, props: &Props) -> TokenStream2 {
+        let name = props.get_name();
+
+        match props.has_hooks() {
+            true => quote! {
+                {
+                    let mut obj = <#name>::from_row(row)?;
+                    oxidizer::hooks::EntityHooks::after_load(&mut obj, db).await?;
+                    obj
+                }
+            },
+            false => quote! { <#name>::from_row(row)? },
+        }
+    }
+
+    /// Builds the write back of the columns assigned by the database from a returned row
+    fn build_returning_read(&self, props: &Props, receiver: &TokenStream2) -> TokenStream2 {
+        let reads: Vec<TokenStream2> = props
+            .get_fields_returning()
+            .iter()
+            .map(|field| {
+                let ident = &field.ident;
+                let ty = &field.ty;
+                quote! {
+                    #receiver.#ident = first_row.get::<&str, #ty>(stringify!(#ident));
+                }
+            })
+            .collect();
+
+        quote! { #(#reads)* }
+    }
+
+    /// Builds the error raised when a versioned row was not matched
+    fn build_version_stale(&self, props: &Props) -> TokenStream2 {
+        match props.get_version_field() {
+            Some(_) => quote! {
+                return Err(oxidizer::db::Error::StaleEntity);
+            },
+            None => quote! {},
+        }
+    }
+
+    /// Builds the error of an UPDATE of the columns that matched no row
+    fn build_update_not_found(&self, props: &Props) -> TokenStream2 {
+        let primary_key_ident = &props.get_primary_key_field().unwrap().ident;
+
+        // a versioned row that still exists was modified concurrently
+        let stale = match props.get_version_field() {
+            Some(_) => quote! {
+                let condition = concat!(stringify!(#primary_key_ident), " = $1");
+                if Self::exists(db, condition, &[&self.#primary_key_ident]).await? {
+                    return Err(oxidizer::db::Error::StaleEntity);
+                }
+            },
+            None => quote! {},
+        };
+
+        quote! {
+            #stale
+            return Err(oxidizer::db::Error::DoesNotExist);
+        }
+    }
+
+    fn build_snapshot_capture(&self, snapshot_field: &syn::Field) -> TokenStream2 {
+        let snapshot_ident = &snapshot_field.ident;
+        quote! {
+            self.#snapshot_ident = oxidizer::snapshot::Snapshot::capture(self.__snapshot_values());
+        }
+    }
+
+    /// Builds the part of `save` that only updates the columns changed since the snapshot was taken
+    fn build_save_changed(&self, props: &Props, snapshot_field: &syn::Field) -> TokenStream2 {
+        let snapshot_ident = &snapshot_field.ident;
+        let not_found = self.build_update_not_found(props);
+        // flags the no-op save for the hooks wrapper of `build_save_fn`, skipping `after_save`
+        let mark_unchanged = match props.has_hooks() {
+            true => quote! { unchanged = true; },
+            false => quote! {},
+        };
+
+        quote! {
+            if !creating && self.#snapshot_ident.is_captured() {
+                let snapshot_values = self.__snapshot_values();
+                let changed = self.#snapshot_ident.get_changed(&snapshot_values);
+                if changed.is_empty() {
+                    #mark_unchanged
+                    return Ok(false);
+                }
+
+                if !self.__update_columns(db, &changed).await? {
+                    #not_found
+                }
+
+                self.#snapshot_ident = oxidizer::snapshot::Snapshot::capture(snapshot_values);
+                return Ok(false);
+            }
+        }
+    }
+
+    /// Builds `insert_many` or `upsert_many`, writing the entities in a transaction with multi-row
+    /// INSERTs chunked under the parameters limit of a statement. Postgres accepts 65535 parameters
+    /// but tokio-postgres encodes their count as an i16.
+    fn build_many_fn(&self, props: &Props, upsert: bool) -> TokenStream2 {
+        let (fn_ident, query) = match upsert {
+            true => (
+                format_ident!("upsert_many"),
+                DefaultBuilder::build_upsert_many_query(props),
+            ),
+            false => (
+                format_ident!("insert_many"),
+                DefaultBuilder::build_insert_many_query(props),
+            ),
+        };
+
+        let receiver = quote! { entity };
+        let values = self.build_fields_values(props, &receiver);
+        let columns_count = values.len();
+
+        // converted values are stored per entity as a tuple, the others are borrowed from the entity
+        let owned_values: Vec<&TokenStream2> = values
+            .iter()
+            .filter(|(_, owned)| *owned)
+            .map(|(value, _)| value)
+            .collect();
+        let mut owned_index = 0;
+        let params: Vec<TokenStream2> = values
+            .iter()
+            .map(|(value, owned)| match owned {
+                true => {
+                    let index = syn::Index::from(owned_index);
+                    owned_index += 1;
+                    quote! { &owned.#index }
+                }
+                false => quote! { &#value },
+            })
+            .collect();
+
+        let primary_key = props.get_primary_key_field().unwrap();
+        let primary_key_ident = &primary_key.ident;
+        let primary_key_type = &primary_key.ty;
+        let returning_read = self.build_returning_read(props, &receiver);
+
+        let snapshot_capture = match props.get_snapshot_field() {
+            Some(field) => {
+                let snapshot_ident = &field.ident;
+                quote! {
+                    entity.#snapshot_ident = oxidizer::snapshot::Snapshot::capture(entity.__snapshot_values());
+                }
+            }
+            None => quote! {},
+        };
+
+        let rows_mismatch = match (upsert, props.get_version_field().is_some()) {
+            (true, true) => quote! {
+                if rows.len() != rows_count {
+                    return Err(oxidizer::db::Error::StaleEntity);
+                }
+            },
+            // conflicting rows of entities without other columns are left untouched
+            (true, false) => quote! {},
+            (false, _) => quote! {
+                if rows.len() != rows_count {
+                    return Err(oxidizer::db::Error::Other("Error while inserting entities".to_string()));
+                }
+            },
+        };
+
+        let (hooks_before, hooks_after) = match props.has_hooks() {
+            true => (
+                quote! {
+                    for entity in entities.iter_mut() {
+                        oxidizer::hooks::EntityHooks::before_save(entity, db).await?;
+                    }
+                },
+                quote! {
+                    for entity in entities.iter_mut() {
+                        oxidizer::hooks::EntityHooks::after_save(entity, db).await?;
+                    }
+                },
+            ),
+            false => (quote! {}, quote! {}),
+        };
+
+        quote! {
+            async fn #fn_ident(db: &oxidizer::db::DB, entities: &mut [Self]) -> oxidizer::db::DBResult<()> {
+                #hooks_before
+
+                for entity in entities.iter() {
+                    entity.validate()?;
+                }
+
+                let tx = db.transaction().await?;
+                let primary_key_default: #primary_key_type = Default::default();
+                let chunk_size = std::cmp::max(1, i16::MAX as usize / #columns_count);
+                for chunk in entities.chunks_mut(chunk_size) {
+                    let rows_count = chunk.len();
+                    #query;
+
+                    let mut owned = Vec::with_capacity(rows_count);
+                    for entity in chunk.iter() {
+                        owned.push(( #( #owned_values, )* ));
+                    }
+
+                    let mut params: Vec<&(dyn oxidizer::db_types::ToSql + Sync)> = Vec::with_capacity(rows_count * #columns_count);
+                    #[allow(unused_variables)]
+                    for (entity, owned) in chunk.iter().zip(owned.iter()) {
+                        #( params.push(#params); )*
+                    }
+
+                    let rows = tx.query(&query, &params).await?;
+                    #rows_mismatch
+
+                    // rows are returned in the order of the values, less the ones left untouched
+                    let mut rows = rows.iter().peekable();
+                    for entity in chunk.iter_mut() {
+                        let first_row = match rows.peek() {
+                            Some(row) => *row,
+                            None => break,
+                        };
+                        let key = first_row.get::<&str, #primary_key_type>(stringify!(#primary_key_ident));
+                        if entity.#primary_key_ident != key && entity.#primary_key_ident != primary_key_default {
+                            continue;
+                        }
+
+                        rows.next();
+                        entity.#primary_key_ident = key;
+                        #returning_read
+                        #snapshot_capture
+                    }
+                }
+
+                tx.commit().await?;
+
+                #hooks_after
+
+                Ok(())
+            }
+        }
+    }
+
+    fn build_copy_in_fn(&self, props: &Props) -> TokenStream2 {
+        let table_name = props.get_table_name();
+        let fields = props.get_fields_copied();
+        let columns: Vec<String> = fields
+            .iter()
+            .map(|field| field.ident.as_ref().unwrap().to_string())
+            .collect();
+
+        // converted values are stored in locals before being borrowed by the row
+        let mut conversions = vec![];
+        let values: Vec<TokenStream2> = fields
+            .iter()
+            .enumerate()
+            .map(|(index, field)| {
+                let name = &field.ident;
+                match field.parse_custom_type() {
+                    Some(ct) => {
+                        let ty_ident = format_ident!("{}", ct.ty);
+                        let local = format_ident!("value_{}", index);
+                        conversions.push(quote! {
+                            let #local = <#ty_ident>::try_from(&entity.#name)?;
+                        });
+                        quote! { &#local }
+                    }
+                    None => quote! { &entity.#name },
+                }
+            })
+            .collect();
+
+        quote! {
+            async fn copy_in<I>(db: &oxidizer::db::DB, entities: I) -> oxidizer::db::DBResult<u64>
+            where
+                I: IntoIterator<Item = Self> + Send,
+                I::IntoIter: Send,
+            {
+                let mut writer = db.copy_in(#table_name, &[#( #columns ),*]).await?;
+
+                for entity in entities {
+                    entity.validate()?;
+                    #( #conversions )*
+                    writer.write(&[#( #values ),*]).await?;
+                }
+
+                writer.finish().await
+            }
+        }
+    }
+
+    fn build_copy_out_fn(&self, props: &Props) -> TokenStream2 {
+        let query = DefaultBuilder::build_copy_out_query(props);
+        quote! {
+            async fn copy_out(db: &oxidizer::db::DB) -> oxidizer::db::DBResult<oxidizer::copy::CopyOutStream<Self>> {
+                #query
+                db.copy_out(&query).await
+            }
+        }
+    }
+
+    /// Builds the hidden helper issuing an `UPDATE` of the given non primary key columns
+    fn build_update_helpers(&self, props: &Props) -> TokenStream2 {
+        let name = props.get_name();
+        let primary_key_ident = &props.get_primary_key_field().unwrap().ident;
+        let query = DefaultBuilder::build_update_changed_query(props);
+
+        let returning_read = self.build_returning_read(props, &quote! { self });
+        let version_param = match props.get_version_field() {
+            Some(field) => {
+                let ident = &field.ident;
+                quote! { params.push(&self.#ident); }
+            }
+            None => quote! {},
+        };
+
+        let fields = props.get_fields_updatable();
+        let fields_names: Vec<&Option<syn::Ident>> =
+            fields.iter().map(|field| &field.ident).collect();
+        let fields_bindings: Vec<syn::Ident> = fields
+            .iter()
+            .map(|field| format_ident!("__value_{}", field.ident.as_ref().unwrap()))
+            .collect();
+        let fields_values: Vec<TokenStream2> = fields
+            .iter()
+            .map(|field| {
+                let name = &field.ident;
+                match field.parse_custom_type() {
+                    Some(ct) => {
+                        let ty_ident = format_ident!("{}", ct.ty);
+                        quote! { <#ty_ident>::try_from(&self.#name)? }
+                    }
+                    None => quote! { &self.#name },
+                }
+            })
+            .collect();
+
+        quote! {
+            impl #name {
+                #[doc(hidden)]
+                async fn __update_columns(&mut self, db: &oxidizer::db::DB, changed: &[usize]) -> oxidizer::db::DBResult<bool> {
+                    #( let #fields_bindings = #fields_values; )*
+                    let columns: Vec<&str> = vec![#( stringify!(#fields_names) ),*];
+                    let values: Vec<&(dyn oxidizer::db_types::ToSql + Sync)> = vec![#( &#fields_bindings ),*];
+
+                    let changed_columns: Vec<&str> = changed.iter().map(|i| columns[*i]).collect();
+                    let mut params: Vec<&(dyn oxidizer::db_types::ToSql + Sync)> = changed.iter().map(|i| values[*i]).collect();
+                    params.push(&self.#primary_key_ident);
+                    #version_param
+
+                    #query;
+                    let rows = db.query(&query, &params).await?;
+                    match rows.first() {
+                        Some(first_row) => {
+                            #returning_read
+                            Ok(true)
+                        }
+                        None => Ok(false),
+                    }
+                }
+            }
+        }
+    }
+
+    fn build_insert_fn(&self, props: &Props) -> TokenStream2 {
+        let query = DefaultBuilder::build_insert_query(props);
+        let returning_read = self.build_returning_read(props, &quote! { self });
+        let fields_value_acessors = self.build_fields_value_accessors(props);
+
+        let primary_key = props.get_primary_key_field().unwrap();
+        let primary_key_ident = &primary_key.ident;
+        let primary_key_type = &primary_key.ty;
+
+        let snapshot_capture = match props.get_snapshot_field() {
+            Some(field) => self.build_snapshot_capture(field),
+            None => quote! {},
+        };
+
+        let body = quote! {
+            self.validate()?;
+
+            #query;
+            let rows = db.query(
+                query,
+                &[#( #fields_value_acessors ),*]
+            ).await?;
+
+            match rows.first() {
+                Some(first_row) => {
+                    self.#primary_key_ident = first_row.get::<&str, #primary_key_type>(stringify!(#primary_key_ident));
+                    #returning_read
+                }
+                None => return Err(oxidizer::db::Error::AlreadyExists),
+            }
+
+            #snapshot_capture
+
+            Ok(())
+        };
+        let body = self.build_hooks_wrapper(
+            props,
+            "before_save",
+            "after_save",
+            quote! { () },
+            body,
+            None,
+        );
+
+        quote! {
+            async fn insert(&mut self, db: &oxidizer::db::DB) -> oxidizer::db::DBResult<()> {
+                #body
+            }
+        }
+    }
+
+    fn build_update_fn(&self, props: &Props) -> TokenStream2 {
+        let columns_count = props.get_fields_updatable().len();
+        let not_found = self.build_update_not_found(props);
+
+        let (changed, snapshot_capture) = match props.get_snapshot_field() {
+            Some(field) => {
+                let snapshot_ident = &field.ident;
+                (
+                    quote! {
+                        let changed = self.#snapshot_ident.get_changed(&self.__snapshot_values());
+                        if self.#snapshot_ident.is_captured() && changed.is_empty() {
+                            return Ok(());
+                        }
+                    },
+                    self.build_snapshot_capture(field),
+                )
+            }
+            None => (
+                quote! { let changed: Vec<usize> = (0..#columns_count).collect(); },
+                quote! {},
+            ),
+        };
+
+        let body = quote! {
+            self.validate()?;
+
+            #changed
+
+            if !self.__update_columns(db, &changed).await? {
+                #not_found
+            }
+
+            #snapshot_capture
+
+            Ok(())
+        };
+        let body = self.build_hooks_wrapper(
+            props,
+            "before_save",
+            "after_save",
+            quote! { () },
+            body,
+            None,
+        );
+
+        quote! {
+            async fn update(&mut self, db: &oxidizer::db::DB) -> oxidizer::db::DBResult<()> {
+                #body
+            }
+        }
+    }
+
+    /// Builds the hidden helper encoding the non primary key columns for dirty tracking
+    fn build_snapshot_helpers(&self, props: &Props) -> TokenStream2 {
+        if props.get_snapshot_field().is_none() {
+            return quote! {};
+        }
+
+        let name = props.get_name();
+        let encoders: Vec<TokenStream2> = props
+            .get_fields_updatable()
+            .iter()
+            .map(|field| {
+                let name = &field.ident;
+                match field.parse_custom_type() {
+                    Some(ct) => {
+                        let ty_ident = format_ident!("{}", ct.ty);
+                        quote! {
+                            <#ty_ident>::try_from(&self.#name)
+                                .ok()
+                                .and_then(|v| oxidizer::snapshot::encode(&v))
+                        }
+                    }
+                    None => quote! { oxidizer::snapshot::encode(&self.#name) },
+                }
+            })
+            .collect();
+
+        quote! {
+            impl #name {
+                #[doc(hidden)]
+                fn __snapshot_values(&self) -> Vec<std::option::Option<Vec<u8>>> {
+                    vec![#( #encoders ),*]
+                }
+            }
+        }
+    }
+
+    fn build_get_dirty_fields_fn(&self, props: &Props) -> TokenStream2 {
+        let fields_names: Vec<&Option<syn::Ident>> = props
+            .get_fields_updatable()
+            .iter()
+            .map(|field| &field.ident)
+            .collect();
+
+        let changed = match props.get_snapshot_field() {
+            Some(field) => {
+                let snapshot_ident = &field.ident;
+                quote! { self.#snapshot_ident.get_changed(&self.__snapshot_values()) }
+            }
+            None => quote! { (0..columns.len()).collect::<Vec<usize>>() },
+        };
+
+        quote! {
+            fn get_dirty_fields(&self) -> Vec<String> {
+                let columns: Vec<&str> = vec![#( stringify!(#fields_names) ),*];
+                #changed.into_iter().map(|i| columns[i].to_string()).collect()
+            }
+        }
+    }
+
+    fn build_create_migration_fn(&self, props: &Props) -> TokenStream2 {
+        let table_name = props.get_table_name();
+        let fields_all_names = props.get_fields_all_names();
+        let fields_all_db_types = props.get_fields_all_db_types();
+        let fields_all_nullable = props.get_fields_all_nullable();
+        let fields_all_indexed = props.get_fields_all_indexed();
+        let fields_all_primary: Vec<bool> = props
+            .get_fields_all_primary()
+            .iter()
+            .map(|attr| attr.is_some())
+            .collect();
+
+        let indexes: Vec<TokenStream2> = props
+            .get_indexes()
+            .iter()
+            .map(|index| {
+                let index_name = &index.name;
+                let columns: Vec<&str> = index.columns.split(",").map(|c| c.trim()).collect();
+                let unique = index.unique;
+                quote! {
+                    t.add_index(
+                        #index_name,
+                        oxidizer::types::index(vec![ #(#columns),* ]).unique(#unique)
+                    );
+                }
+            })
+            .collect();
+
+        let polymorphic_indexes: Vec<TokenStream2> = props
+            .get_polymorphic_attrs()
+            .iter()
+            .map(|attr| {
+                let index_name = format!("{}_{}_polymorphic", table_name, attr.name);
+                let type_field = attr.get_type_field_name();
+                let id_field = attr.get_id_field_name();
+                quote! {
+                    t.add_index(
+                        #index_name,
+                        oxidizer::types::index(vec![ #type_field, #id_field ])
+                    );
+                }
+            })
+            .collect();
+
+        let change_statements = match props.emits_changes() {
+            true => {
+                let primary_key_ident = &props.get_primary_key_field().unwrap().ident;
+                quote! {
+                    m.statements.extend(oxidizer::changes::create_trigger_statements(
+                        #table_name,
+                        stringify!(#primary_key_ident),
+                    ));
+                }
+            }
+            false => quote! {},
+        };
+
+        let history = match props.is_audited() {
+            true => {
+                let primary_key_ident = &props.get_primary_key_field().unwrap().ident;
+                let key_type = props.get_primary_key_db_type();
+                quote! {
+                    oxidizer::audit::add_history(
+                        &mut m,
+                        #table_name,
+                        stringify!(#primary_key_ident),
+                        #key_type,
+                    );
+                }
+            }
+            false => quote! {},
+        };
+
+        let versions = match props.is_temporal() {
+            true => {
+                let primary_key_ident = &props.get_primary_key_field().unwrap().ident;
+                let key_type = props.get_primary_key_db_type();
+                quote! {
+                    oxidizer::temporal::add_versions(
+                        &mut m,
+                        #table_name,
+                        stringify!(#primary_key_ident),
+                        #key_type,
+                    );
+                }
+            }
+            false => quote! {},
+        };
+
+        quote! {
+             fn create_migration() -> oxidizer::db::DBResult<oxidizer::migration::Migration> {
+                let mut m = oxidizer::migration::Migration::new(#table_name);
+                m.raw.create_table(#table_name, |t| {
+                    #(t
+                        .add_column(
+                            stringify!(#fields_all_names),
+                            #fields_all_db_types
+                                .nullable(#fields_all_nullable)
+                                .indexed(#fields_all_indexed)
+                                .primary(#fields_all_primary)
+                        )
+                    ;)*
+
+                    #(#indexes)*
+
+                    #(#polymorphic_indexes)*
+                });
+
+                #change_statements
+
+                #history
+
+                #versions
+
+                Ok(m)
+            }
+        }
+    }
+
+    fn build_find_fn(&self, props: &Props) -> TokenStream2 {
+        let name = props.get_name();
+        let query = DefaultBuilder::build_find_query(props);
+        let load_row = self.build_load_row(props);
+        quote! {
+            async fn find(db: &oxidizer::db::DB, condition: &str, params: &'_ [&'_ (dyn oxidizer::db_types::ToSql + Sync)]) -> oxidizer::db::DBResult<Vec<#name>> {
+                #query;
+                let rows = db.query(&query, params).await?;
+
+                let mut results: Vec<#name> = Vec::with_capacity(rows.len());
+
+                for row in rows.iter() {
+                    results.push(#load_row);
+                }
+
+                Ok(results)
+            }
+        }
+    }
+
+    fn build_load_rows_fn(&self, props: &Props) -> TokenStream2 {
+        let name = props.get_name();
+        let load_row = self.build_load_row(props);
+        quote! {
+            async fn load_rows(db: &oxidizer::db::DB, rows: &[oxidizer::tokio_postgres::Row]) -> oxidizer::db::DBResult<Vec<#name>> {
+                let mut results: Vec<#name> = Vec::with_capacity(rows.len());
+
+                for row in rows.iter() {
+                    results.push(#load_row);
+                }
+
+                Ok(results)
+            }
+        }
+    }
+
+    fn build_query_fn(&self, props: &Props) -> TokenStream2 {
+        let scope = match props.get_soft_delete_field() {
+            Some(field) => {
+                let ident = &field.ident;
+                quote! { Some(concat!(stringify!(#ident), " IS NULL")) }
+            }
+            None => quote! { None },
+        };
+        quote! {
+            fn query<'a>() -> oxidizer::query::Query<'a, Self> {
+                oxidizer::query::Query::new(#scope)
+            }
+        }
+    }
+
+    fn build_find_stream_fn(&self, props: &Props) -> TokenStream2 {
+        let query = DefaultBuilder::build_find_query(props);
+        let decode = match props.has_hooks() {
+            true => quote! { oxidizer::db::stream::load_stream::<Self>(db, rows) },
+            false => quote! { oxidizer::db::stream::decode_stream::<Self>(rows) },
+        };
+        quote! {
+            async fn find_stream(db: &oxidizer::db::DB, condition: &str, params: &'_ [&'_ (dyn oxidizer::db_types::ToSql + Sync)]) -> oxidizer::db::DBResult<oxidizer::db::DBStream<Self>>
+            where
+                Self: Send + 'static,
+            {
+                #query;
+                let rows = db.query_stream(&query, params).await?;
+
+                Ok(#decode)
+            }
+        }
+    }
+
+    fn build_first_fn(&self, props: &Props) -> TokenStream2 {
+        let name = props.get_name();
+        let query = DefaultBuilder::build_first_query(props);
+        let load_row = self.build_load_row(props);
+        quote! {
+            async fn first(db: &oxidizer::db::DB, condition: &str, params: &'_ [&'_ (dyn oxidizer::db_types::ToSql + Sync)]) -> oxidizer::db::DBResult<std::option::Option<#name>> {
+                #query;
+                let rows = db.query(&query, params).await?;
+
+                let mut results: Vec<#name> = Vec::with_capacity(rows.len());
+                for row in rows.iter() {
+                    results.push(#load_row);
+                }
+
+                match results.len() {
+                    0 => Ok(None),
+                    _ => Ok(Some(results.remove(0))),
+                }
+            }
+        }
+    }
+
+    fn build_pk_fns(&self, props: &Props) -> TokenStream2 {
+        let name = props.get_name();
+        let primary_key_ident = &props.get_primary_key_field().unwrap().ident;
+
+        let fields_all_names = props.get_fields_all_names();
+        let snapshot_reload = match props.get_snapshot_field() {
+            Some(field) => {
+                let snapshot_ident = &field.ident;
+                quote! { self.#snapshot_ident = fresh.#snapshot_ident; }
+            }
+            None => quote! {},
+        };
+
+        quote! {
+            async fn find_by_pk(db: &oxidizer::db::DB, key: &Self::PrimaryKey) -> oxidizer::db::DBResult<std::option::Option<#name>> {
+                let condition = concat!(stringify!(#primary_key_ident), " = $1");
+                <#name>::first(db, condition, &[key]).await
+            }
+
+            async fn get_by_pk(db: &oxidizer::db::DB, key: &Self::PrimaryKey) -> oxidizer::db::DBResult<#name> {
+                match <#name>::find_by_pk(db, key).await? {
+                    Some(obj) => Ok(obj),
+                    None => Err(oxidizer::db::Error::DoesNotExist),
+                }
+            }
+
+            async fn reload(&mut self, db: &oxidizer::db::DB) -> oxidizer::db::DBResult<()> {
+                let fresh = <#name>::get_by_pk(db, &self.#primary_key_ident).await?;
+                #( self.#fields_all_names = fresh.#fields_all_names; )*
+                #snapshot_reload
+                Ok(())
+            }
+        }
+    }
+
+    fn build_lookup_helpers(&self, props: &Props) -> TokenStream2 {
+        let name = props.get_name();
+
+        let lookups: Vec<TokenStream2> = props
+            .get_fields_lookup()
+            .iter()
+            .map(|field| {
+                let ident = field.ident.as_ref().unwrap();
+                let fn_ident = format_ident!("find_by_{}", ident);
+                let ty = &field.ty;
+
+                let condition = match field.is_nullable() {
+                    true => format!("{} IS NOT DISTINCT FROM $1", ident),
+                    false => format!("{} = $1", ident),
+                };
+
+                let (conversion, param) = match field.parse_custom_type() {
+                    Some(ct) => {
+                        let ty_ident = format_ident!("{}", ct.ty);
+                        (quote! { let value = <#ty_ident>::try_from(value)?; }, quote! { &value })
+                    }
+                    None => (quote! {}, quote! { value }),
+                };
+
+                match props.is_field_unique(field) {
+                    true => quote! {
+                        pub async fn #fn_ident(db: &oxidizer::db::DB, value: &#ty) -> oxidizer::db::DBResult<std::option::Option<#name>> {
+                            #conversion
+                            <#name as oxidizer::entity::IEntity>::first(db, #condition, &[#param]).await
+                        }
+                    },
+                    false => quote! {
+                        pub async fn #fn_ident(db: &oxidizer::db::DB, value: &#ty) -> oxidizer::db::DBResult<Vec<#name>> {
+                            #conversion
+                            <#name as oxidizer::entity::IEntity>::find(db, #condition, &[#param]).await
+                        }
+                    },
+                }
+            })
+            .collect();
+
+        if lookups.is_empty() {
+            return quote! {};
+        }
+
+        quote! {
+            impl #name {
+                #( #lookups )*
+            }
+        }
+    }
+
+    fn build_aggregate_fns(&self, props: &Props) -> TokenStream2 {
+        let count_query = DefaultBuilder::build_count_query(props);
+        let exists_query = DefaultBuilder::build_exists_query(props);
+        let aggregate_query = DefaultBuilder::build_aggregate_query(props);
+        let group_by_query = DefaultBuilder::build_group_by_query(props);
+
+        let single_value_fns: Vec<TokenStream2> = ["sum", "min", "max"]
+            .iter()
+            .map(|function| {
+                let ident = format_ident!("{}", function);
+                let sql_function = function.to_uppercase();
+
+                quote! {
+                    async fn #ident<T>(db: &oxidizer::db::DB, column: &str, condition: &str, params: &'_ [&'_ (dyn oxidizer::db_types::ToSql + Sync)]) -> oxidizer::db::DBResult<std::option::Option<T>>
+                    where
+                        T: oxidizer::db_types::FromSqlOwned + Send,
+                    {
+                        let aggregate = format!("{}({})", #sql_function, column);
+                        #aggregate_query;
+                        let rows = db.query(&query, params).await?;
+
+                        rows[0].try_get::<usize, std::option::Option<T>>(0).map_err(oxidizer::db::Error::PostgresError)
+                    }
+                }
+            })
+            .collect();
+
+        quote! {
+            async fn count(db: &oxidizer::db::DB, condition: &str, params: &'_ [&'_ (dyn oxidizer::db_types::ToSql + Sync)]) -> oxidizer::db::DBResult<i64> {
+                #count_query;
+                let rows = db.query(&query, params).await?;
+
+                rows[0].try_get::<usize, i64>(0).map_err(oxidizer::db::Error::PostgresError)
+            }
+
+            async fn exists(db: &oxidizer::db::DB, condition: &str, params: &'_ [&'_ (dyn oxidizer::db_types::ToSql + Sync)]) -> oxidizer::db::DBResult<bool> {
+                #exists_query;
+                let rows = db.query(&query, params).await?;
+
+                rows[0].try_get::<usize, bool>(0).map_err(oxidizer::db::Error::PostgresError)
+            }
+
+            #(#single_value_fns)*
+
+            async fn avg(db: &oxidizer::db::DB, column: &str, condition: &str, params: &'_ [&'_ (dyn oxidizer::db_types::ToSql + Sync)]) -> oxidizer::db::DBResult<std::option::Option<f64>> {
+                let aggregate = format!("CAST(AVG({}) AS DOUBLE PRECISION)", column);
+                #aggregate_query;
+                let rows = db.query(&query, params).await?;
+
+                rows[0].try_get::<usize, std::option::Option<f64>>(0).map_err(oxidizer::db::Error::PostgresError)
+            }
+
+            async fn group_by<T>(db: &oxidizer::db::DB, columns: &str, aggregates: &str, condition: &str, params: &'_ [&'_ (dyn oxidizer::db_types::ToSql + Sync)]) -> oxidizer::db::DBResult<Vec<T>>
+            where
+                T: oxidizer::row::FromRow + Send,
+            {
+                #group_by_query;
+                db.query_as::<T>(&query, params).await
+            }
+        }
+    }
+
+    fn build_where_fns(&self, props: &Props) -> TokenStream2 {
+        let name = props.get_name();
+        let update_query = DefaultBuilder::build_update_where_query(props);
+        let delete_query = DefaultBuilder::build_delete_where_query(props);
+        let load_row = self.build_load_row(props);
+
+        quote! {
+            async fn update_where(db: &oxidizer::db::DB, condition: &str, assignments: &str, params: &'_ [&'_ (dyn oxidizer::db_types::ToSql + Sync)]) -> oxidizer::db::DBResult<u64> {
+                let returning = "";
+                #update_query
+                db.execute(&query, params).await
+            }
+
+            async fn update_where_returning(db: &oxidizer::db::DB, condition: &str, assignments: &str, params: &'_ [&'_ (dyn oxidizer::db_types::ToSql + Sync)]) -> oxidizer::db::DBResult<Vec<#name>> {
+                let returning = " RETURNING *";
+                #update_query
+                let rows = db.query(&query, params).await?;
+
+                let mut results: Vec<#name> = Vec::with_capacity(rows.len());
+                for row in rows.iter() {
+                    results.push(#load_row);
+                }
+
+                Ok(results)
+            }
+
+            async fn delete_where(db: &oxidizer::db::DB, condition: &str, params: &'_ [&'_ (dyn oxidizer::db_types::ToSql + Sync)]) -> oxidizer::db::DBResult<u64> {
+                let returning = "";
+                #delete_query
+                db.execute(&query, params).await
+            }
+
+            async fn delete_where_returning(db: &oxidizer::db::DB, condition: &str, params: &'_ [&'_ (dyn oxidizer::db_types::ToSql + Sync)]) -> oxidizer::db::DBResult<Vec<#name>> {
+                let returning = " RETURNING *";
+                #delete_query
+                let rows = db.query(&query, params).await?;
+
+                let mut results: Vec<#name> = Vec::with_capacity(rows.len());
+                for row in rows.iter() {
+                    results.push(#load_row);
+                }
+
+                Ok(results)
+            }
+        }
+    }
+
+    /// Builds the body removing the row of the entity from the database
+    fn build_hard_delete(&self, props: &Props) -> TokenStream2 {
+        let primary_key_ident = &props.get_primary_key_field().unwrap().ident;
+        let primary_key_type = &props.get_primary_key_field().unwrap().ty;
+        let query = DefaultBuilder::build_delete_query(props);
+        let snapshot_clear = match props.get_snapshot_field() {
+            Some(field) => {
+                let snapshot_ident = &field.ident;
+                quote! { self.#snapshot_ident.clear(); }
+            }
+            None => quote! {},
+        };
+        quote! {
+            let key_default: #primary_key_type = Default::default();
+            if self.#primary_key_ident == key_default {
+                return Ok(false);
+            }
+
+            #query;
+
+            match db.execute(&query, &[&self.#primary_key_ident]).await? {
+                0 => Ok(false),
+                _ => {
+                    self.#primary_key_ident = key_default;
+                    #snapshot_clear
+                    Ok(true)
+                },
+            }
+        }
+    }
+
+    fn build_delete_fn(&self, props: &Props) -> TokenStream2 {
+        let body = match props.get_soft_delete_field() {
+            Some(field) => {
+                let primary_key_ident = &props.get_primary_key_field().unwrap().ident;
+                let primary_key_type = &props.get_primary_key_field().unwrap().ty;
+                let soft_delete_ident = &field.ident;
+                let soft_delete_type = &field.ty;
+                let query = DefaultBuilder::build_soft_delete_query(props);
+                quote! {
+                    let key_default: #primary_key_type = Default::default();
+                    if self.#primary_key_ident == key_default {
+                        return Ok(false);
+                    }
+
+                    #query;
 
-                    return quote! { &<#ty_ident>::try_from(&self.#name)? };
+                    let rows = db.query(&query, &[&self.#primary_key_ident]).await?;
+                    match rows.first() {
+                        Some(first_row) => {
+                            self.#soft_delete_ident = first_row.get::<&str, #soft_delete_type>(stringify!(#soft_delete_ident));
+                            Ok(true)
+                        }
+                        None => Ok(false),
+                    }
                 }
+            }
+            None => self.build_hard_delete(props),
+        };
+        let body = self.build_hooks_wrapper(
+            props,
+            "before_delete",
+            "after_delete",
+            quote! { bool },
+            body,
+            None,
+        );
 
-                if field.parse_primary_key().is_some() && field.is_increments() {
-                    let ty = &field.ty;
-                    return quote! {
-                        &match self.#name { v if v == <#ty>::default() => None, _ => Some(self.#name) }
-                    };
-                }
+        quote! {
+            async fn delete(&mut self, db: &oxidizer::db::DB) -> oxidizer::db::DBResult<bool> {
+                #body
+            }
+        }
+    }
 
-                quote! { &self.#name }
-            })
-            .collect();
+    /// Builds the trashed scopes, `restore` and `force_delete` of soft deletable entities
+    fn build_soft_delete_helpers(&self, props: &Props) -> TokenStream2 {
+        let field = match props.get_soft_delete_field() {
+            Some(field) => field,
+            None => return quote! {},
+        };
 
-        let primary_key = props.get_primary_key_field().unwrap();
-        let primary_key_ident = &primary_key.ident;
-        let primary_key_type = &primary_key.ty;
+        let name = props.get_name();
+        let primary_key_ident = &props.get_primary_key_field().unwrap().ident;
+        let soft_delete_ident = &field.ident;
+        let query = DefaultBuilder::build_restore_query(props);
+        let hard_delete = self.build_hard_delete(props);
+        let hard_delete = self.build_hooks_wrapper(
+            props,
+            "before_delete",
+            "after_delete",
+            quote! { bool },
+            hard_delete,
+            None,
+        );
 
         quote! {
-            async fn save(&mut self, db: &oxidizer::db::DB) -> oxidizer::db::DBResult<bool> {
-                let mut creating = false;
-                let primary_key_default: #primary_key_type = Default::default();
+            impl #name {
+                /// Scope including the trashed rows
+                pub fn with_trashed() -> oxidizer::soft_delete::Trashed<Self> {
+                    oxidizer::soft_delete::Trashed::new(
+                        stringify!(#soft_delete_ident),
+                        oxidizer::soft_delete::TrashedScope::WithTrashed,
+                    )
+                }
 
-                if self.#primary_key_ident == primary_key_default {
-                    creating = true;
+                /// Scope only including the trashed rows
+                pub fn only_trashed() -> oxidizer::soft_delete::Trashed<Self> {
+                    oxidizer::soft_delete::Trashed::new(
+                        stringify!(#soft_delete_ident),
+                        oxidizer::soft_delete::TrashedScope::OnlyTrashed,
+                    )
                 }
 
-                #query;
-                let rows = db.query(
-                    query,
-                    &[#( #fields_value_acessors ),*]
-                ).await?;
-                if let Some(first_row) = rows.first()  {
-                    self.#primary_key_ident = first_row.get::<&str, #primary_key_type>(stringify!(#primary_key_ident));
-                } else if creating {
-                   return Err(oxidizer::db::Error::Other("Error while saving entity".to_string()));
+                /// Restores a trashed entity, returning whether a row was restored
+                pub async fn restore(&mut self, db: &oxidizer::db::DB) -> oxidizer::db::DBResult<bool> {
+                    #query;
+
+                    match db.execute(&query, &[&self.#primary_key_ident]).await? {
+                        0 => Ok(false),
+                        _ => {
+                            self.#soft_delete_ident = None;
+                            Ok(true)
+                        }
+                    }
                 }
 
-                Ok(creating)
+                /// Removes the row from the database, trashed or not
+                pub async fn force_delete(&mut self, db: &oxidizer::db::DB) -> oxidizer::db::DBResult<bool> {
+                    #hard_delete
+                }
             }
         }
     }
 
-    fn build_from_row_fn(&self, props: &Props) -> TokenStream2 {
-        let fields_all_loaders: Vec<TokenStream2> = props
-            .get_fields_all()
-            .map(|field| {
-                let name = &field.ident;
-
-                let ty = field.get_type();
+    fn build_changes_fn(&self, props: &Props) -> TokenStream2 {
+        if !props.emits_changes() {
+            return quote! {};
+        }
 
-                let mut converter = quote! {};
-                let mut converter_pos = quote! {};
+        let name = props.get_name();
 
-                if let Some(_) = field.parse_custom_type() {
-                    let custom_ty = &field.ty;
-                    converter = quote! { <#custom_ty>::try_from };
-                    converter_pos  = quote! {?};
+        quote! {
+            impl #name {
+                /// Subscribes to the rows inserted, updated and deleted
+                pub async fn changes(db: &oxidizer::db::DB) -> oxidizer::db::DBResult<oxidizer::db::DBStream<oxidizer::changes::Change<Self>>> {
+                    oxidizer::changes::listen::<Self>(db).await
                 }
+            }
+        }
+    }
 
-                quote! {
-                    #name: #converter(row.get::<&str, #ty>(concat!(stringify!(#name))))#converter_pos,
-                }
-            })
-            .collect();
+    fn build_history_fn(&self, props: &Props) -> TokenStream2 {
+        if !props.is_audited() {
+            return quote! {};
+        }
 
-        let fields_ignored_names: Vec<&Option<syn::Ident>> = props
-            .get_ignored_fields()
-            .map(|field| &field.ident)
-            .collect();
-        let fields_ignored_types: Vec<&syn::Type> =
-            props.get_ignored_fields().map(|field| &field.ty).collect();
+        let name = props.get_name();
+        let primary_key_ident = &props.get_primary_key_field().unwrap().ident;
 
         quote! {
-            fn from_row(row: &oxidizer::tokio_postgres::Row) -> oxidizer::db::DBResult<Self> {
-                let mut obj: Self = Self{
-                    #( #fields_all_loaders )*
-                    #(
-                        #fields_ignored_names: <#fields_ignored_types>::default(),
-                    )*
-                };
-                Ok(obj)
+            impl #name {
+                /// Changes of the row, oldest first
+                pub async fn history(&self, db: &oxidizer::db::DB) -> oxidizer::db::DBResult<Vec<oxidizer::audit::HistoryEntry<Self>>> {
+                    oxidizer::audit::history::<Self>(db, &self.#primary_key_ident).await
+                }
             }
         }
     }
 
-    fn build_create_migration_fn(&self, props: &Props) -> TokenStream2 {
-        let table_name = props.get_table_name();
-        let fields_all_names = props.get_fields_all_names();
-        let fields_all_db_types = props.get_fields_all_db_types();
-        let fields_all_nullable = props.get_fields_all_nullable();
-        let fields_all_indexed = props.get_fields_all_indexed();
-        let fields_all_primary: Vec<bool> = props
-            .get_fields_all_primary()
-            .iter()
-            .map(|attr| attr.is_some())
-            .collect();
+    fn build_as_of_fns(&self, props: &Props) -> TokenStream2 {
+        if !props.is_temporal() {
+            return quote! {};
+        }
 
-        let indexes: Vec<TokenStream2> = props
-            .get_indexes()
-            .iter()
-            .map(|index| {
-                let index_name = &index.name;
-                let columns: Vec<&str> = index.columns.split(",").map(|c| c.trim()).collect();
-                let unique = index.unique;
-                quote! {
-                    t.add_index(
-                        #index_name,
-                        oxidizer::types::index(vec![ #(#columns),* ]).unique(#unique)
-                    );
-                }
-            })
-            .collect();
+        let name = props.get_name();
+        let primary_key_type = &props.get_primary_key_field().unwrap().ty;
 
         quote! {
-             fn create_migration() -> oxidizer::db::DBResult<oxidizer::migration::Migration> {
-                let mut m = oxidizer::migration::Migration::new(#table_name);
-                m.raw.create_table(#table_name, |t| {
-                    #(t
-                        .add_column(
-                            stringify!(#fields_all_names),
-                            #fields_all_db_types
-                                .nullable(#fields_all_nullable)
-                                .indexed(#fields_all_indexed)
-                                .primary(#fields_all_primary)
-                        )
-                    ;)*
-
-                    #(#indexes)*
-                });
+            impl #name {
+                /// Rows as they were at `at`
+                pub async fn as_of(db: &oxidizer::db::DB, at: oxidizer::temporal::Timestamp) -> oxidizer::db::DBResult<Vec<#name>> {
+                    oxidizer::temporal::as_of::<Self>(db, at).await
+                }
 
-                Ok(m)
+                /// Row with the primary key `key` as it was at `at`
+                pub async fn find_by_pk_as_of(db: &oxidizer::db::DB, key: &#primary_key_type, at: oxidizer::temporal::Timestamp) -> oxidizer::db::DBResult<std::option::Option<#name>> {
+                    oxidizer::temporal::find_by_pk_as_of::<Self>(db, key, at).await
+                }
             }
         }
     }
 
-    fn build_find_fn(&self, props: &Props) -> TokenStream2 {
-        let name = props.get_name();
-        let query = DefaultBuilder::build_find_query(props);
-        quote! {
-            async fn find(db: &oxidizer::db::DB, condition: &str, params: &'_ [&'_ (dyn oxidizer::db_types::ToSql + Sync)]) -> oxidizer::db::DBResult<Vec<#name>> {
-                #query;
-                let rows = db.query(&query, params).await?;
+    fn build_validate_fn(&self, props: &Props) -> TokenStream2 {
+        let checks: Vec<TokenStream2> = props
+            .get_fields_all()
+            .filter_map(|field| {
+                let attrs = field.parse_validate();
+                if attrs.is_empty() {
+                    return None;
+                }
 
-                let mut results: Vec<#name> = Vec::with_capacity(rows.len());
+                let ident = &field.ident;
+                let field_name = ident.as_ref().unwrap().to_string();
+                let push = |message: String| {
+                    quote! {
+                        errors.push(oxidizer::validation::FieldError::new(#field_name, #message));
+                    }
+                };
 
-                for row in rows.iter() {
-                    results.push(Self::from_row(row)?);
-                }
+                let mut validators: Vec<TokenStream2> = vec![];
+                for attr in attrs.iter() {
+                    if let Some(length) = attr.length.as_ref() {
+                        if let Some(min) = length.min {
+                            let error = push(format!("must be at least {} characters long", min));
+                            validators.push(quote! {
+                                if value.chars().count() < #min {
+                                    #error
+                                }
+                            });
+                        }
+                        if let Some(max) = length.max {
+                            let error = push(format!("must be at most {} characters long", max));
+                            validators.push(quote! {
+                                if value.chars().count() > #max {
+                                    #error
+                                }
+                            });
+                        }
+                    }
 
-                Ok(results)
-            }
-        }
-    }
+                    if attr.email {
+                        let error = push("must be a valid email address".to_string());
+                        validators.push(quote! {
+                            if !oxidizer::validation::is_email(value) {
+                                #error
+                            }
+                        });
+                    }
 
-    fn build_first_fn(&self, props: &Props) -> TokenStream2 {
-        let name = props.get_name();
-        let query = DefaultBuilder::build_first_query(props);
-        quote! {
-            async fn first(db: &oxidizer::db::DB, condition: &str, params: &'_ [&'_ (dyn oxidizer::db_types::ToSql + Sync)]) -> oxidizer::db::DBResult<std::option::Option<#name>> {
-                #query;
-                let rows = db.query(&query, params).await?;
+                    // the bounds are cast to the field type, `range(min = 0)` also applies to floats
+                    if let Some(range) = attr.range.as_ref() {
+                        let value_ty = option_inner_type(&field.ty);
+                        if let Some(min) = range.min.as_ref() {
+                            let error = push(format!("must be at least {}", quote! { #min }));
+                            validators.push(quote! {
+                                if *value < (#min as #value_ty) {
+                                    #error
+                                }
+                            });
+                        }
+                        if let Some(max) = range.max.as_ref() {
+                            let error = push(format!("must be at most {}", quote! { #max }));
+                            validators.push(quote! {
+                                if *value > (#max as #value_ty) {
+                                    #error
+                                }
+                            });
+                        }
+                    }
 
-                let mut results: Vec<#name> = Vec::with_capacity(rows.len());
-                for row in rows.iter() {
-                    results.push(Self::from_row(row)?);
+                    if let Some(custom) = attr.custom.as_ref() {
+                        let path: syn::Path = match syn::parse_str(custom) {
+                            Ok(path) => path,
+                            Err(_) => {
+                                return Some(quote_spanned! {
+                                    field.span() => compile_error!("Invalid custom validator");
+                                })
+                            }
+                        };
+                        validators.push(quote! {
+                            if let Err(message) = #path(value) {
+                                errors.push(oxidizer::validation::FieldError::new(#field_name, &message));
+                            }
+                        });
+                    }
                 }
 
-                match results.len() {
-                    0 => Ok(None),
-                    _ => Ok(Some(results.remove(0))),
+                match field.is_nullable() {
+                    true => Some(quote! {
+                        if let Some(value) = &self.#ident {
+                            #(#validators)*
+                        }
+                    }),
+                    false => Some(quote! {
+                        {
+                            let value = &self.#ident;
+                            #(#validators)*
+                        }
+                    }),
                 }
-            }
+            })
+            .collect();
+
+        if checks.is_empty() {
+            return quote! {
+                fn validate(&self) -> oxidizer::db::DBResult<()> {
+                    Ok(())
+                }
+            };
         }
-    }
 
-    fn build_delete_fn(&self, props: &Props) -> TokenStream2 {
-        let primary_key_ident = &props.get_primary_key_field().unwrap().ident;
-        let primary_key_type = &props.get_primary_key_field().unwrap().ty;
-        let query = DefaultBuilder::build_delete_query(props);
         quote! {
-            async fn delete(&mut self, db: &oxidizer::db::DB) -> oxidizer::db::DBResult<bool> {
-                let key_default: #primary_key_type = Default::default();
-                if self.#primary_key_ident == key_default {
-                    return Ok(false);
-                }
+            fn validate(&self) -> oxidizer::db::DBResult<()> {
+                let mut errors: Vec<oxidizer::validation::FieldError> = vec![];
 
-                #query;
+                #(#checks)*
 
-                match db.execute(&query, &[&self.#primary_key_ident]).await? {
-                    0 => Ok(false),
-                    _ => {
-                        self.#primary_key_ident = key_default;
-                        Ok(true)
-                    },
+                match errors.is_empty() {
+                    true => Ok(()),
+                    false => Err(oxidizer::db::Error::Validation(errors)),
                 }
             }
         }
@@ -250,12 +1452,18 @@ impl EntityBuilder {
             let relation = field.parse_relation().unwrap();
             let local_key = field.ident.clone().unwrap();
             let local_key_type = &field.ty;
-            let get_ident = format_ident!("get_{}", to_snake_case(&relation.model));
-            let set_ident = format_ident!("set_{}", to_snake_case(&relation.model));
-            let trait_ident = format_ident!("__Accessor{}To{}", name, relation.model);
-            let model = format_ident!("{}", relation.model);
+            let model = props.get_relation_model(&relation);
             let key = format_ident!("{}", relation.key);
 
+            // self-referential accessors are named after the field (parent_id -> get_parent)
+            let accessor_name = match relation.is_self_referential() {
+                true => local_key.to_string().trim_end_matches("_id").to_string(),
+                false => to_snake_case(&relation.model),
+            };
+            let get_ident = format_ident!("get_{}", accessor_name);
+            let set_ident = format_ident!("set_{}", accessor_name);
+            let trait_ident = format_ident!("__Accessor{}To{}", name, model);
+
             let local_key_set = match field.is_nullable() {
                 true => quote! {
                     self.#local_key = Some(v.#key);
@@ -265,13 +1473,19 @@ impl EntityBuilder {
                 },
             };
 
-            let query  = DefaultBuilder::build_relation_get_query(props, &relation);
+            let condition = DefaultBuilder::build_relation_get_condition(props, &relation);
+
+            let (tree_fns_decl, tree_fns_impl) = match relation.is_self_referential() {
+                true => self.build_tree_fns(props, field),
+                false => (quote! {}, quote! {}),
+            };
 
             quote! {
                 #[oxidizer::async_trait]
                 pub trait #trait_ident {
                     async fn #get_ident(&self, db: &oxidizer::db::DB) -> oxidizer::db::DBResult<#model>;
                     async fn #set_ident(&mut self, db: &oxidizer::db::DB, v: &#model) -> oxidizer::db::DBResult<()>;
+                    #tree_fns_decl
                 }
 
                 #[oxidizer::async_trait]
@@ -281,14 +1495,12 @@ impl EntityBuilder {
                             return Err(oxidizer::db::Error::DoesNotExist);
                         }
 
-                        #query;
+                        #condition;
 
-                        let results = db.query(&query, &[&self.#local_key]).await?;
-                        if results.len() == 0 {
-                            return Err(oxidizer::db::Error::DoesNotExist);
+                        match <#model>::first(db, &condition, &[&self.#local_key]).await? {
+                            Some(v) => Ok(v),
+                            None => Err(oxidizer::db::Error::DoesNotExist),
                         }
-
-                        #model::from_row(&results[0])
                     }
 
                     async fn #set_ident(&mut self, db: &oxidizer::db::DB, v: &#model) -> oxidizer::db::DBResult<()> {
@@ -300,11 +1512,46 @@ impl EntityBuilder {
                         self.save(db).await?;
                         Ok(())
                     }
+
+                    #tree_fns_impl
                 }
             }
         }).collect()
     }
 
+    /// Builds the recursive `ancestors`/`descendants` accessors of a self-referential relation
+    fn build_tree_fns(&self, props: &Props, field: &syn::Field) -> (TokenStream2, TokenStream2) {
+        let name = props.get_name();
+        let local_key = &field.ident;
+        let key = format_ident!("{}", field.parse_relation().unwrap().key);
+
+        let ancestors_query = DefaultBuilder::build_relation_ancestors_query(props, field);
+        let descendants_query = DefaultBuilder::build_relation_descendants_query(props, field);
+
+        let decl = quote! {
+            async fn ancestors(&self, db: &oxidizer::db::DB) -> oxidizer::db::DBResult<Vec<#name>>;
+            async fn descendants(&self, db: &oxidizer::db::DB, max_depth: Option<i32>) -> oxidizer::db::DBResult<Vec<#name>>;
+        };
+
+        let imp = quote! {
+            async fn ancestors(&self, db: &oxidizer::db::DB) -> oxidizer::db::DBResult<Vec<#name>> {
+                #ancestors_query;
+                let rows = db.query(query, &[&self.#local_key]).await?;
+
+                <#name>::load_rows(db, &rows).await
+            }
+
+            async fn descendants(&self, db: &oxidizer::db::DB, max_depth: Option<i32>) -> oxidizer::db::DBResult<Vec<#name>> {
+                #descendants_query;
+                let rows = db.query(query, &[&self.#key, &max_depth]).await?;
+
+                <#name>::load_rows(db, &rows).await
+            }
+        };
+
+        (decl, imp)
+    }
+
     fn build_has_many_helpers(&self, props: &Props) -> Vec<TokenStream2> {
         let name = props.get_name();
 
@@ -344,6 +1591,98 @@ impl EntityBuilder {
         }).collect()
     }
 
+    fn build_polymorphic_helpers(&self, props: &Props) -> Vec<TokenStream2> {
+        let name = props.get_name();
+        let vis = props.get_visibility();
+
+        props.get_polymorphic_attrs().iter().map(|attr| {
+            let pascal_name = to_pascal_case(&attr.name);
+
+            let enum_ident = match attr.enum_name.as_ref() {
+                Some(enum_name) => format_ident!("{}", enum_name),
+                None => format_ident!("{}{}", name, pascal_name),
+            };
+            let trait_ident = format_ident!("__AccessorPolymorphic{}{}", name, pascal_name);
+            let get_ident = format_ident!("get_{}", attr.name);
+            let set_ident = format_ident!("set_{}", attr.name);
+
+            let type_field = props.get_field_by_name(&attr.get_type_field_name()).unwrap();
+            let id_field = props.get_field_by_name(&attr.get_id_field_name()).unwrap();
+            let type_ident = &type_field.ident;
+            let id_ident = &id_field.ident;
+            let id_type = &id_field.ty;
+
+            let type_value = match type_field.is_nullable() {
+                true => quote! { self.#type_ident.as_deref() },
+                false => quote! { Some(self.#type_ident.as_str()) },
+            };
+
+            let models: Vec<syn::Ident> = attr
+                .get_models()
+                .iter()
+                .map(|model| format_ident!("{}", model))
+                .collect();
+
+            let loaders: Vec<TokenStream2> = models.iter().map(|model| {
+                let query = DefaultBuilder::build_relation_polymorphic_get_condition(props, attr, model);
+
+                quote! {
+                    Some(t) if t == <#model>::get_table_name() => {
+                        #query;
+
+                        match <#model>::first(db, &query, &[&self.#id_ident]).await? {
+                            Some(v) => Ok(#enum_ident::#model(v)),
+                            None => Err(oxidizer::db::Error::DoesNotExist),
+                        }
+                    }
+                }
+            }).collect();
+
+            let unknown_type_message = format!("Unknown {} type: {{}}", attr.get_type_field_name());
+
+            quote! {
+                #vis enum #enum_ident {
+                    #( #models(#models), )*
+                }
+
+                #[oxidizer::async_trait]
+                pub trait #trait_ident {
+                    async fn #get_ident(&self, db: &oxidizer::db::DB) -> oxidizer::db::DBResult<#enum_ident>;
+                    async fn #set_ident<T>(&mut self, db: &oxidizer::db::DB, v: &T) -> oxidizer::db::DBResult<()>
+                    where
+                        T: oxidizer::entity::IEntity + Sync,
+                        T::PrimaryKey: Into<#id_type>;
+                }
+
+                #[oxidizer::async_trait]
+                impl #trait_ident for #name {
+                    async fn #get_ident(&self, db: &oxidizer::db::DB) -> oxidizer::db::DBResult<#enum_ident> {
+                        match #type_value {
+                            #( #loaders )*
+                            Some(t) => Err(oxidizer::db::Error::Other(format!(#unknown_type_message, t))),
+                            None => Err(oxidizer::db::Error::DoesNotExist),
+                        }
+                    }
+
+                    async fn #set_ident<T>(&mut self, db: &oxidizer::db::DB, v: &T) -> oxidizer::db::DBResult<()>
+                    where
+                        T: oxidizer::entity::IEntity + Sync,
+                        T::PrimaryKey: Into<#id_type>,
+                    {
+                        if !v.is_synced_with_db() {
+                            return Err(oxidizer::db::Error::ReferencedModelIsNotInDB);
+                        }
+
+                        self.#type_ident = T::get_table_name().into();
+                        self.#id_ident = v.get_primary_key().clone().into();
+                        self.save(db).await?;
+                        Ok(())
+                    }
+                }
+            }
+        }).collect()
+    }
+
     pub fn build(&self, item: TokenStream) -> TokenStream {
         let input = parse_macro_input!(item as DeriveInput);
 
@@ -353,10 +1692,14 @@ impl EntityBuilder {
 
         let mut has_many_attrs: Vec<HasManyAttr> = vec![];
 
+        let mut polymorphic_attrs: Vec<PolymorphicAttr> = vec![];
+
         for option in input.attrs.iter() {
             let option = option.parse_meta().unwrap();
-            if let Ok(v) = EntityAttr::from_meta(&option) {
-                attrs = Some(v);
+            if option.path().is_ident("entity") {
+                if let Ok(v) = EntityAttr::from_meta(&option) {
+                    attrs = Some(v);
+                }
             }
 
             if let Ok(v) = IndexAttr::from_meta(&option) {
@@ -366,57 +1709,154 @@ impl EntityBuilder {
             if let Ok(v) = HasManyAttr::from_meta(&option) {
                 has_many_attrs.push(v);
             }
+
+            if let Ok(v) = PolymorphicAttr::from_meta(&option) {
+                polymorphic_attrs.push(v);
+            }
         }
 
         // eprintln!("{:#?}", input);
         // eprintln!("{:#?}", attrs);
 
-        let props = Props::new(input, attrs, indexes, has_many_attrs);
+        let props = Props::new(input, attrs, indexes, has_many_attrs, polymorphic_attrs);
 
         if let Some(ts) = props.check() {
             return ts;
         }
 
         let save_fn = self.build_save_fn(&props);
+        let insert_fn = self.build_insert_fn(&props);
+        let update_fn = self.build_update_fn(&props);
+        let insert_many_fn = self.build_many_fn(&props, false);
+        let upsert_many_fn = self.build_many_fn(&props, true);
         let delete_fn = self.build_delete_fn(&props);
+        let where_fns = self.build_where_fns(&props);
+        let copy_in_fn = self.build_copy_in_fn(&props);
+        let copy_out_fn = self.build_copy_out_fn(&props);
         let is_synced_with_db = self.build_is_synced_with_db_fn(&props);
-        let from_row_fn = self.build_from_row_fn(&props);
+        let validate_fn = self.build_validate_fn(&props);
+        let from_row_fn = FromRowBuilder::new().build_from_row_fn(&props);
+        let from_copy_row_fn = FromRowBuilder::new().build_from_copy_row_fn(&props);
         let create_migration_fn = self.build_create_migration_fn(&props);
         let find_fn = self.build_find_fn(&props);
+        let find_stream_fn = self.build_find_stream_fn(&props);
+        let load_rows_fn = self.build_load_rows_fn(&props);
+        let query_fn = self.build_query_fn(&props);
         let first_fn = self.build_first_fn(&props);
+        let pk_fns = self.build_pk_fns(&props);
+        let lookup_helpers = self.build_lookup_helpers(&props);
+        let aggregate_fns = self.build_aggregate_fns(&props);
+        let get_dirty_fields_fn = self.build_get_dirty_fields_fn(&props);
+        let snapshot_helpers = self.build_snapshot_helpers(&props);
+        let update_helpers = self.build_update_helpers(&props);
+        let soft_delete_helpers = self.build_soft_delete_helpers(&props);
+        let changes_fn = self.build_changes_fn(&props);
+        let history_fn = self.build_history_fn(&props);
+        let as_of_fns = self.build_as_of_fns(&props);
 
         let name = props.get_name();
         let table_name = props.get_table_name();
+        let primary_key_ident = &props.get_primary_key_field().unwrap().ident;
+        let primary_key_type = &props.get_primary_key_field().unwrap().ty;
+        let soft_delete_name = match props.get_soft_delete_field() {
+            Some(field) => {
+                let ident = &field.ident;
+                quote! { Some(stringify!(#ident).to_string()) }
+            }
+            None => quote! { None },
+        };
 
         let foreign_helpers = self.build_foreign_helpers(&props);
 
         let has_many_helpers = self.build_has_many_helpers(&props);
 
+        let polymorphic_helpers = self.build_polymorphic_helpers(&props);
+
         let expanded = quote! {
             #[oxidizer::async_trait]
             impl oxidizer::entity::IEntity for #name {
+                type PrimaryKey = #primary_key_type;
+
                 #save_fn
 
+                #insert_fn
+
+                #update_fn
+
+                #insert_many_fn
+
+                #upsert_many_fn
+
                 #delete_fn
 
+                #where_fns
+
+                #copy_in_fn
+
+                #copy_out_fn
+
                 #is_synced_with_db
 
+                #validate_fn
+
+                #get_dirty_fields_fn
+
                 #find_fn
 
+                #find_stream_fn
+
                 #first_fn
 
+                #pk_fns
+
+                #aggregate_fns
+
                 #from_row_fn
 
+                #from_copy_row_fn
+
+                #load_rows_fn
+
+                #query_fn
+
                 #create_migration_fn
 
                 fn get_table_name() -> String {
                     #table_name.to_string()
                 }
+
+                fn get_primary_key_name() -> String {
+                    stringify!(#primary_key_ident).to_string()
+                }
+
+                fn get_soft_delete_name() -> Option<String> {
+                    #soft_delete_name
+                }
+
+                fn get_primary_key(&self) -> &Self::PrimaryKey {
+                    &self.#primary_key_ident
+                }
             }
 
+            #snapshot_helpers
+
+            #update_helpers
+
+            #soft_delete_helpers
+
+            #changes_fn
+
+            #history_fn
+
+            #as_of_fns
+
+            #lookup_helpers
+
             #(#foreign_helpers)*
 
             #(#has_many_helpers)*
+
+            #(#polymorphic_helpers)*
         };
 
         // Hand the output tokens back to the compiler
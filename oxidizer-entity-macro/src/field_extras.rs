@@ -3,7 +3,7 @@ use proc_macro2::TokenStream as TokenStream2;
 use quote::{format_ident, quote, quote_spanned};
 use syn::{spanned::Spanned, Field, Type};
 
-use super::attrs::{CustomTypeAttr, PrimaryKeyAttr, RelationAttr};
+use super::attrs::{CustomTypeAttr, PrimaryKeyAttr, RelationAttr, ValidateAttr};
 use super::utils::search_attr_in_field;
 use super::utils::type_to_db_type;
 use super::utils::{check_type_order, is_integer_type};
@@ -12,10 +12,16 @@ pub trait FieldExtras {
     fn is_indexed(&self) -> bool;
     fn is_nullable(&self) -> bool;
     fn is_ignore(&self) -> bool;
+    fn is_snapshot(&self) -> bool;
+    fn is_version(&self) -> bool;
+    fn is_created_at(&self) -> bool;
+    fn is_updated_at(&self) -> bool;
+    fn is_soft_delete(&self) -> bool;
     fn is_increments(&self) -> bool;
     fn parse_primary_key(&self) -> Option<PrimaryKeyAttr>;
     fn parse_relation(&self) -> Option<RelationAttr>;
     fn parse_custom_type(&self) -> Option<CustomTypeAttr>;
+    fn parse_validate(&self) -> Vec<ValidateAttr>;
     fn get_db_type(&self) -> TokenStream2;
     fn get_type(&self) -> TokenStream2;
 }
@@ -26,7 +32,27 @@ impl FieldExtras for Field {
     }
 
     fn is_ignore(&self) -> bool {
-        search_attr_in_field(self, "field_ignore")
+        search_attr_in_field(self, "field_ignore") || self.is_snapshot()
+    }
+
+    fn is_snapshot(&self) -> bool {
+        search_attr_in_field(self, "snapshot")
+    }
+
+    fn is_version(&self) -> bool {
+        search_attr_in_field(self, "version")
+    }
+
+    fn is_created_at(&self) -> bool {
+        search_attr_in_field(self, "created_at")
+    }
+
+    fn is_updated_at(&self) -> bool {
+        search_attr_in_field(self, "updated_at")
+    }
+
+    fn is_soft_delete(&self) -> bool {
+        search_attr_in_field(self, "soft_delete")
     }
 
     fn is_increments(&self) -> bool {
@@ -70,6 +96,14 @@ impl FieldExtras for Field {
         None
     }
 
+    fn parse_validate(&self) -> Vec<ValidateAttr> {
+        (&self.attrs)
+            .into_iter()
+            .filter(|attr| attr.path.is_ident("validate"))
+            .filter_map(|attr| ValidateAttr::from_meta(&attr.parse_meta().unwrap()).ok())
+            .collect()
+    }
+
     fn is_nullable(&self) -> bool {
         match &self.ty {
             syn::Type::Path(tp) => {
@@ -128,6 +162,15 @@ impl FieldExtras for Field {
             return type_to_db_type(&ty);
         }
 
+        if self.is_created_at() || self.is_updated_at() {
+            return quote! { oxidizer::types::custom("timestamp with time zone DEFAULT now()") };
+        }
+
+        if self.is_version() {
+            let ty = type_to_db_type(&self.ty);
+            return quote! { #ty.default(0i64) };
+        }
+
         type_to_db_type(&self.ty)
     }
 }
@@ -0,0 +1,144 @@
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{parse_macro_input, DeriveInput};
+
+use super::field_extras::*;
+use super::props::*;
+
+pub struct FromRowBuilder {}
+
+impl FromRowBuilder {
+    pub fn new() -> Self {
+        FromRowBuilder {}
+    }
+
+    pub fn build_from_row_fn(&self, props: &Props) -> TokenStream2 {
+        let fields_all_loaders: Vec<TokenStream2> = props
+            .get_fields_all()
+            .map(|field| {
+                let name = &field.ident;
+
+                let ty = field.get_type();
+
+                let mut converter = quote! {};
+                let mut converter_pos = quote! {};
+
+                if let Some(_) = field.parse_custom_type() {
+                    let custom_ty = &field.ty;
+                    converter = quote! { <#custom_ty>::try_from };
+                    converter_pos  = quote! {?};
+                }
+
+                quote! {
+                    #name: #converter(row.try_get::<&str, #ty>(stringify!(#name)).map_err(oxidizer::db::Error::PostgresError)?)#converter_pos,
+                }
+            })
+            .collect();
+
+        let fields_ignored_names: Vec<&Option<syn::Ident>> = props
+            .get_ignored_fields()
+            .map(|field| &field.ident)
+            .collect();
+        let fields_ignored_types: Vec<&syn::Type> =
+            props.get_ignored_fields().map(|field| &field.ty).collect();
+
+        let snapshot_capture = match props.get_snapshot_field() {
+            Some(field) => {
+                let snapshot_ident = &field.ident;
+                quote! {
+                    obj.#snapshot_ident = oxidizer::snapshot::Snapshot::capture(obj.__snapshot_values());
+                }
+            }
+            None => quote! {},
+        };
+
+        quote! {
+            fn from_row(row: &oxidizer::tokio_postgres::Row) -> oxidizer::db::DBResult<Self> {
+                let mut obj: Self = Self{
+                    #( #fields_all_loaders )*
+                    #(
+                        #fields_ignored_names: <#fields_ignored_types>::default(),
+                    )*
+                };
+                #snapshot_capture
+                Ok(obj)
+            }
+        }
+    }
+
+    /// Builds `from_copy_row`, decoding the columns of a binary `COPY` by position in the order of
+    /// `get_fields_all`
+    pub fn build_from_copy_row_fn(&self, props: &Props) -> TokenStream2 {
+        let fields_all_loaders: Vec<TokenStream2> = props
+            .get_fields_all()
+            .enumerate()
+            .map(|(index, field)| {
+                let name = &field.ident;
+
+                let ty = field.get_type();
+
+                let mut converter = quote! {};
+                let mut converter_pos = quote! {};
+
+                if field.parse_custom_type().is_some() {
+                    let custom_ty = &field.ty;
+                    converter = quote! { <#custom_ty>::try_from };
+                    converter_pos = quote! {?};
+                }
+
+                quote! {
+                    #name: #converter(row.try_get::<#ty>(#index).map_err(oxidizer::db::Error::PostgresError)?)#converter_pos,
+                }
+            })
+            .collect();
+
+        let fields_ignored_names: Vec<&Option<syn::Ident>> = props
+            .get_ignored_fields()
+            .map(|field| &field.ident)
+            .collect();
+        let fields_ignored_types: Vec<&syn::Type> =
+            props.get_ignored_fields().map(|field| &field.ty).collect();
+
+        let snapshot_capture = match props.get_snapshot_field() {
+            Some(field) => {
+                let snapshot_ident = &field.ident;
+                quote! {
+                    obj.#snapshot_ident = oxidizer::snapshot::Snapshot::capture(obj.__snapshot_values());
+                }
+            }
+            None => quote! {},
+        };
+
+        quote! {
+            fn from_copy_row(row: &oxidizer::tokio_postgres::binary_copy::BinaryCopyOutRow) -> oxidizer::db::DBResult<Self> {
+                let mut obj: Self = Self{
+                    #( #fields_all_loaders )*
+                    #(
+                        #fields_ignored_names: <#fields_ignored_types>::default(),
+                    )*
+                };
+                #snapshot_capture
+                Ok(obj)
+            }
+        }
+    }
+
+    pub fn build(&self, item: TokenStream) -> TokenStream {
+        let input = parse_macro_input!(item as DeriveInput);
+
+        let props = Props::new(input, None, vec![], vec![], vec![]);
+
+        let from_row_fn = self.build_from_row_fn(&props);
+
+        let name = props.get_name();
+
+        let expanded = quote! {
+            impl oxidizer::row::FromRow for #name {
+                #from_row_fn
+            }
+        };
+
+        TokenStream::from(expanded)
+    }
+}
@@ -3,6 +3,8 @@ use proc_macro::TokenStream;
 mod attrs;
 mod entity_builder;
 mod field_extras;
+mod from_row_builder;
+mod partial_builder;
 mod props;
 mod sql_builder;
 mod utils;
@@ -17,11 +19,30 @@ mod utils;
         entity,
         index,
         has_many,
+        polymorphic,
         field_ignore,
         custom_type,
         increments,
+        snapshot,
+        version,
+        created_at,
+        updated_at,
+        soft_delete,
+        validate,
     )
 )]
 pub fn entity_macro(item: TokenStream) -> TokenStream {
     entity_builder::EntityBuilder::new().build(item)
 }
+
+/// Partial derive macro
+#[proc_macro_derive(Partial, attributes(partial_of, field_ignore, custom_type))]
+pub fn partial_macro(item: TokenStream) -> TokenStream {
+    partial_builder::PartialBuilder::new().build(item)
+}
+
+/// FromRow derive macro
+#[proc_macro_derive(FromRow, attributes(field_ignore, custom_type))]
+pub fn from_row_macro(item: TokenStream) -> TokenStream {
+    from_row_builder::FromRowBuilder::new().build(item)
+}
@@ -0,0 +1,117 @@
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{quote, quote_spanned};
+use syn::{parse_macro_input, DeriveInput, Meta, NestedMeta, Path};
+
+use super::from_row_builder::FromRowBuilder;
+use super::props::*;
+use super::sql_builder::{Builder, DefaultBuilder};
+
+pub struct PartialBuilder {}
+
+impl PartialBuilder {
+    pub fn new() -> Self {
+        PartialBuilder {}
+    }
+
+    fn parse_partial_of(&self, input: &DeriveInput) -> Option<Path> {
+        for option in input.attrs.iter() {
+            let option = option.parse_meta().unwrap();
+            match option {
+                Meta::List(list) if list.path.is_ident("partial_of") => {
+                    return match list.nested.first() {
+                        Some(NestedMeta::Meta(Meta::Path(path))) => Some(path.clone()),
+                        _ => None,
+                    };
+                }
+                _ => {}
+            }
+        }
+        None
+    }
+
+    fn build_get_columns_fn(&self, props: &Props) -> TokenStream2 {
+        let columns = props.get_fields_all_names();
+        quote! {
+            fn get_columns() -> Vec<String> {
+                vec![ #( stringify!(#columns).to_string() ),* ]
+            }
+        }
+    }
+
+    fn build_find_fn(&self, props: &Props, entity: &Path) -> TokenStream2 {
+        let name = props.get_name();
+        let query = DefaultBuilder::build_partial_find_query(props, entity);
+        quote! {
+            async fn find(db: &oxidizer::db::DB, condition: &str, params: &'_ [&'_ (dyn oxidizer::db_types::ToSql + Sync)]) -> oxidizer::db::DBResult<Vec<#name>> {
+                #query;
+                let rows = db.query(&query, params).await?;
+
+                let mut results: Vec<#name> = Vec::with_capacity(rows.len());
+
+                for row in rows.iter() {
+                    results.push(Self::from_row(row)?);
+                }
+
+                Ok(results)
+            }
+        }
+    }
+
+    fn build_first_fn(&self, props: &Props, entity: &Path) -> TokenStream2 {
+        let name = props.get_name();
+        let query = DefaultBuilder::build_partial_first_query(props, entity);
+        quote! {
+            async fn first(db: &oxidizer::db::DB, condition: &str, params: &'_ [&'_ (dyn oxidizer::db_types::ToSql + Sync)]) -> oxidizer::db::DBResult<std::option::Option<#name>> {
+                #query;
+                let rows = db.query(&query, params).await?;
+
+                match rows.first() {
+                    Some(row) => Ok(Some(Self::from_row(row)?)),
+                    None => Ok(None),
+                }
+            }
+        }
+    }
+
+    pub fn build(&self, item: TokenStream) -> TokenStream {
+        let input = parse_macro_input!(item as DeriveInput);
+
+        let entity = match self.parse_partial_of(&input) {
+            Some(entity) => entity,
+            None => {
+                return TokenStream::from(quote_spanned! {
+                    input.ident.span() => compile_error!("Missing #[partial_of(Entity)] attribute")
+                })
+            }
+        };
+
+        let props = Props::new(input, None, vec![], vec![], vec![]);
+
+        let from_row_fn = FromRowBuilder::new().build_from_row_fn(&props);
+        let get_columns_fn = self.build_get_columns_fn(&props);
+        let find_fn = self.build_find_fn(&props, &entity);
+        let first_fn = self.build_first_fn(&props, &entity);
+
+        let name = props.get_name();
+
+        let expanded = quote! {
+            impl oxidizer::row::FromRow for #name {
+                #from_row_fn
+            }
+
+            #[oxidizer::async_trait]
+            impl oxidizer::partial::IPartial for #name {
+                type Entity = #entity;
+
+                #get_columns_fn
+
+                #find_fn
+
+                #first_fn
+            }
+        };
+
+        TokenStream::from(expanded)
+    }
+}
@@ -1,22 +1,24 @@
+use darling::FromMeta;
 use inflector::cases::snakecase::to_snake_case;
 use proc_macro::TokenStream;
 use proc_macro2::TokenStream as TokenStream2;
-use quote::{quote, quote_spanned};
+use quote::{format_ident, quote, quote_spanned};
 use syn::{
     punctuated::Punctuated, spanned::Spanned, token::Comma, Data, DataStruct, DeriveInput, Field,
-    Fields, Ident, Meta, PathArguments, PathSegment, Type,
+    Fields, Ident, Meta, PathArguments, PathSegment, Type, Visibility,
 };
 
-use super::attrs::HasManyAttr;
-use super::attrs::{EntityAttr, IndexAttr, PrimaryKeyAttr};
+use super::attrs::{EntityAttr, IndexAttr, PrimaryKeyAttr, RelationAttr};
+use super::attrs::{HasManyAttr, PolymorphicAttr, ValidateAttr};
 use super::field_extras::*;
-use super::utils::is_integer_type;
+use super::utils::{is_integer_type, is_typed_with};
 
 pub struct Props {
     input: DeriveInput,
     attrs: Option<EntityAttr>,
     indexes: Vec<IndexAttr>,
     has_many_attrs: Vec<HasManyAttr>,
+    polymorphic_attrs: Vec<PolymorphicAttr>,
 }
 
 type GetFieldsIter<'a> = std::iter::Filter<syn::punctuated::Iter<'a, Field>, fn(&&Field) -> bool>;
@@ -27,12 +29,14 @@ impl Props {
         attrs: Option<EntityAttr>,
         indexes: Vec<IndexAttr>,
         has_many_attrs: Vec<HasManyAttr>,
+        polymorphic_attrs: Vec<PolymorphicAttr>,
     ) -> Self {
         Props {
             input: input,
             attrs: attrs,
             indexes: indexes,
             has_many_attrs: has_many_attrs,
+            polymorphic_attrs,
         }
     }
 
@@ -40,6 +44,10 @@ impl Props {
         &self.input.ident
     }
 
+    pub fn get_visibility(&self) -> &Visibility {
+        &self.input.vis
+    }
+
     pub fn get_table_name(&self) -> String {
         let snaked_name = to_snake_case(&self.get_name().to_string());
 
@@ -76,6 +84,10 @@ impl Props {
         fields.iter().filter(|field| field.is_ignore())
     }
 
+    pub fn get_snapshot_field(&self) -> Option<&Field> {
+        self.get_ignored_fields().find(|field| field.is_snapshot())
+    }
+
     pub fn get_fields_all_names(&self) -> Vec<&Option<Ident>> {
         self.get_fields_all().map(|field| &field.ident).collect()
     }
@@ -129,6 +141,37 @@ impl Props {
             .collect()
     }
 
+    /// Non primary key fields whose values are written by an `UPDATE`
+    pub fn get_fields_updatable(&self) -> Vec<&Field> {
+        self.get_fields_plain()
+            .into_iter()
+            .filter(|field| !field.is_version() && !field.is_created_at() && !field.is_updated_at())
+            .collect()
+    }
+
+    /// Fields assigned by the database and read back from `RETURNING`
+    pub fn get_fields_returning(&self) -> Vec<&Field> {
+        self.get_fields_all()
+            .filter(|field| field.is_version() || field.is_created_at() || field.is_updated_at())
+            .collect()
+    }
+
+    /// Fields written by `copy_in`, the others are filled by the database defaults
+    pub fn get_fields_copied(&self) -> Vec<&Field> {
+        self.get_fields_all()
+            .filter(|field| !(field.parse_primary_key().is_some() && field.is_increments()))
+            .filter(|field| !field.is_created_at() && !field.is_updated_at())
+            .collect()
+    }
+
+    pub fn get_soft_delete_field(&self) -> Option<&Field> {
+        self.get_fields_all().find(|field| field.is_soft_delete())
+    }
+
+    pub fn get_version_field(&self) -> Option<&Field> {
+        self.get_fields_all().find(|field| field.is_version())
+    }
+
     pub fn get_fields_plain_names(&self) -> Vec<&Option<Ident>> {
         self.get_fields_plain()
             .iter()
@@ -181,6 +224,90 @@ impl Props {
             }
         }
 
+        let mut version_fields = self.get_fields_all().filter(|field| field.is_version());
+        if let Some(field) = version_fields.next() {
+            let (check, _) = is_integer_type(&field.ty);
+            if !check || field.parse_primary_key().is_some() {
+                return Some(TokenStream::from(quote_spanned! {
+                    field.ty.span() => compile_error!(
+                        "Version can only be used with non primary key integer types"
+                    )
+                }));
+            }
+        }
+        if let Some(field) = version_fields.next() {
+            return Some(TokenStream::from(quote_spanned! {
+                field.ident.as_ref().unwrap().span() => compile_error!(
+                    "Only one version field per entity is supported"
+                )
+            }));
+        }
+
+        for field in self.get_fields_all() {
+            if !field.is_created_at() && !field.is_updated_at() && !field.is_soft_delete() {
+                continue;
+            }
+
+            let valid = match &field.ty {
+                Type::Path(tp) => is_typed_with(
+                    tp.path.segments.first().unwrap(),
+                    vec!["Option", "DateTime", "Utc"],
+                ),
+                _ => false,
+            };
+            if !valid || field.parse_primary_key().is_some() {
+                return Some(TokenStream::from(quote_spanned! {
+                    field.ty.span() => compile_error!(
+                        "Timestamps and soft delete can only be used with non primary key Option<DateTime<Utc>> fields"
+                    )
+                }));
+            }
+        }
+
+        for field in self.get_fields_all() {
+            for attr in field
+                .attrs
+                .iter()
+                .filter(|attr| attr.path.is_ident("validate"))
+            {
+                if let Err(e) = ValidateAttr::from_meta(&attr.parse_meta().unwrap()) {
+                    return Some(TokenStream::from(e.write_errors()));
+                }
+            }
+        }
+
+        // ancestors/descendants accessors are generated per entity
+        if let Some(field) = self
+            .get_fields_foreign()
+            .iter()
+            .filter(|field| field.parse_relation().unwrap().is_self_referential())
+            .nth(1)
+        {
+            return Some(TokenStream::from(quote_spanned! {
+                field.ident.as_ref().unwrap().span() => compile_error!(
+                    "Only one self-referential relation per entity is supported"
+                )
+            }));
+        }
+
+        for attr in self.polymorphic_attrs.iter() {
+            if self
+                .get_field_by_name(&attr.get_type_field_name())
+                .is_none()
+                || self.get_field_by_name(&attr.get_id_field_name()).is_none()
+            {
+                let message = format!(
+                    "Polymorphic relation `{}` requires the fields `{}` and `{}`",
+                    attr.name,
+                    attr.get_type_field_name(),
+                    attr.get_id_field_name()
+                );
+                return Some(TokenStream::from(quote_spanned! {
+                    self.get_name().span() => compile_error!(#message)
+                }));
+            }
+        }
+
         // TODO this limitation should go away eventually
         if self
             .get_fields_all()
@@ -208,11 +335,81 @@ impl Props {
             .collect()
     }
 
+    /// Resolves the model of a relation, mapping `Self` to the entity being derived
+    pub fn get_relation_model(&self, relation: &RelationAttr) -> Ident {
+        match relation.is_self_referential() {
+            true => self.get_name().clone(),
+            false => format_ident!("{}", relation.model),
+        }
+    }
+
+    pub fn has_hooks(&self) -> bool {
+        self.attrs
+            .as_ref()
+            .map(|attrs| attrs.hooks)
+            .unwrap_or(false)
+    }
+
+    pub fn emits_changes(&self) -> bool {
+        self.attrs
+            .as_ref()
+            .map(|attrs| attrs.emit_changes)
+            .unwrap_or(false)
+    }
+
+    pub fn is_audited(&self) -> bool {
+        self.attrs
+            .as_ref()
+            .map(|attrs| attrs.audited)
+            .unwrap_or(false)
+    }
+
+    pub fn is_temporal(&self) -> bool {
+        self.attrs
+            .as_ref()
+            .map(|attrs| attrs.temporal)
+            .unwrap_or(false)
+    }
+
+    /// Database type of the primary key when referenced from another table
+    pub fn get_primary_key_db_type(&self) -> TokenStream2 {
+        let field = self.get_primary_key_field().unwrap();
+        match field.is_increments() {
+            true => quote! { oxidizer::types::integer() },
+            false => field.get_db_type(),
+        }
+    }
+
     pub fn get_indexes(&self) -> Vec<IndexAttr> {
         self.indexes.clone()
     }
 
+    /// Whether a single column unique index covers `field`
+    pub fn is_field_unique(&self, field: &Field) -> bool {
+        let name = field.ident.as_ref().unwrap().to_string();
+        self.indexes
+            .iter()
+            .any(|index| index.unique && index.columns.trim() == name)
+    }
+
+    /// Non primary key fields looked up by the generated `find_by_<field>` methods
+    pub fn get_fields_lookup(&self) -> Vec<&Field> {
+        self.get_fields_plain()
+            .into_iter()
+            .filter(|field| field.is_indexed() || self.is_field_unique(field))
+            .collect()
+    }
+
     pub fn get_has_many_attrs(&self) -> Vec<HasManyAttr> {
         self.has_many_attrs.clone()
     }
+
+    pub fn get_polymorphic_attrs(&self) -> Vec<PolymorphicAttr> {
+        self.polymorphic_attrs.clone()
+    }
+
+    pub fn get_field_by_name(&self, name: &str) -> Option<&Field> {
+        self.get_fields_all()
+            .find(|field| field.ident.as_ref().unwrap() == name)
+    }
 }
@@ -3,10 +3,10 @@ use inflector::cases::snakecase::to_snake_case;
 use proc_macro::TokenStream;
 use proc_macro2::TokenStream as TokenStream2;
 use quote::{format_ident, quote, quote_spanned};
-use syn::{parse_macro_input, spanned::Spanned, DeriveInput, Type};
+use syn::{parse_macro_input, spanned::Spanned, DeriveInput, Field, Ident, Path, Type};
 
-use super::attrs::HasManyAttr;
 use super::attrs::{EntityAttr, IndexAttr, RelationAttr};
+use super::attrs::{HasManyAttr, PolymorphicAttr};
 use super::field_extras::*;
 use super::props::*;
 
@@ -17,15 +17,55 @@ pub trait Builder {
 
     fn build_save_query(props: &Props) -> TokenStream2;
 
+    fn build_insert_query(props: &Props) -> TokenStream2;
+
+    fn build_insert_many_query(props: &Props) -> TokenStream2;
+
+    fn build_upsert_many_query(props: &Props) -> TokenStream2;
+
+    fn build_update_changed_query(props: &Props) -> TokenStream2;
+
     fn build_find_query(props: &Props) -> TokenStream2;
 
     fn build_first_query(props: &Props) -> TokenStream2;
 
     fn build_delete_query(props: &Props) -> TokenStream2;
 
-    fn build_relation_get_query(props: &Props, relation: &RelationAttr) -> TokenStream2;
+    fn build_soft_delete_query(props: &Props) -> TokenStream2;
+
+    fn build_restore_query(props: &Props) -> TokenStream2;
+
+    fn build_update_where_query(props: &Props) -> TokenStream2;
+
+    fn build_delete_where_query(props: &Props) -> TokenStream2;
+
+    fn build_copy_out_query(props: &Props) -> TokenStream2;
+
+    fn build_count_query(props: &Props) -> TokenStream2;
+
+    fn build_exists_query(props: &Props) -> TokenStream2;
+
+    fn build_aggregate_query(props: &Props) -> TokenStream2;
+
+    fn build_group_by_query(props: &Props) -> TokenStream2;
+
+    fn build_partial_find_query(props: &Props, entity: &Path) -> TokenStream2;
+
+    fn build_partial_first_query(props: &Props, entity: &Path) -> TokenStream2;
+
+    fn build_relation_get_condition(props: &Props, relation: &RelationAttr) -> TokenStream2;
+
+    fn build_relation_ancestors_query(props: &Props, field: &Field) -> TokenStream2;
+
+    fn build_relation_descendants_query(props: &Props, field: &Field) -> TokenStream2;
 
     fn build_relation_has_many_get_condition(props: &Props, attr: &HasManyAttr) -> TokenStream2;
+
+    fn build_relation_polymorphic_get_condition(
+        props: &Props,
+        attr: &PolymorphicAttr,
+        model: &Ident,
+    ) -> TokenStream2;
 }
 
 pub type DefaultBuilder = postgres::PostgresBuilder;
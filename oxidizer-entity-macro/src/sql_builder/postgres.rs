@@ -3,10 +3,10 @@ use inflector::cases::snakecase::to_snake_case;
 use proc_macro::TokenStream;
 use proc_macro2::TokenStream as TokenStream2;
 use quote::{format_ident, quote, quote_spanned};
-use syn::{parse_macro_input, spanned::Spanned, DeriveInput, Type};
+use syn::{parse_macro_input, spanned::Spanned, DeriveInput, Field, Ident, Path, Type};
 
-use crate::attrs::HasManyAttr;
 use crate::attrs::{EntityAttr, IndexAttr, RelationAttr};
+use crate::attrs::{HasManyAttr, PolymorphicAttr};
 use crate::field_extras::*;
 use crate::props::*;
 use crate::utils::is_integer_type;
@@ -15,23 +15,45 @@ use super::Builder;
 
 pub struct PostgresBuilder {}
 
-impl Builder for PostgresBuilder {
-    fn new() -> Self {
-        PostgresBuilder {}
+impl PostgresBuilder {
+    /// Restricts the `condition` of a query to non trashed rows of soft deletable entities
+    fn build_scope(props: &Props) -> TokenStream2 {
+        match props.get_soft_delete_field() {
+            Some(field) => {
+                let ident = &field.ident;
+                quote! {
+                    let condition = format!("{} IS NULL AND ({})", stringify!(#ident), condition);
+                }
+            }
+            None => quote! {},
+        }
     }
 
-    fn build_save_query(props: &crate::props::Props) -> TokenStream2 {
+    /// Builds the restriction of a recursive relation query to the non trashed rows of `alias`
+    fn build_tree_scope(props: &Props, alias: &str) -> String {
+        match props.get_soft_delete_field() {
+            Some(field) => format!(" AND {}.{} IS NULL", alias, field.ident.as_ref().unwrap()),
+            None => String::new(),
+        }
+    }
+
+    /// Builds the columns assigned by the database appended to the primary key in `RETURNING`
+    fn build_returning_columns(props: &Props) -> String {
+        props
+            .get_fields_returning()
+            .iter()
+            .map(|field| format!(", {}", field.ident.as_ref().unwrap()))
+            .collect()
+    }
+
+    /// Builds the value of each column of an INSERT with a `{}` in place of the parameter index,
+    /// defaulting auto-increment keys to their sequence
+    fn build_values_templates(props: &Props) -> Vec<String> {
         let table_name = props.get_table_name();
 
-        let fields_ident: Vec<&Option<syn::Ident>> =
-            props.get_fields_all().map(|field| &field.ident).collect();
-        let mut current_index = 1;
-        let fields_query_values = props
+        props
             .get_fields_all()
             .map(|field| {
-                let v = current_index;
-                current_index += 1;
-
                 match field.parse_primary_key().is_some() && field.is_increments() {
                     true => {
                         let bigserial_types = vec!["i64"];
@@ -41,76 +63,221 @@ impl Builder for PostgresBuilder {
                         };
 
                         format!(
-                            "COALESCE(${}, CAST(nextval(pg_get_serial_sequence('{}', '{}')) AS {}))",
-                            v,
+                            "COALESCE(${{}}, CAST(nextval(pg_get_serial_sequence('{}', '{}')) AS {}))",
                             table_name,
                             field.ident.as_ref().unwrap().to_string(),
                             cast,
                         )
                     }
-                    false => format!("${}", v),
+                    false if field.is_created_at() || field.is_updated_at() => {
+                        "COALESCE(${}, now())".to_string()
+                    }
+                    false => "${}".to_string(),
                 }
             })
-            .collect::<Vec<String>>()
-            .join(",");
+            .collect()
+    }
 
-        let mut current_index = 0;
-        let mut comma_index = 0;
-        let fields_plain_to_set: Vec<TokenStream2> = props
-            .get_fields_all()
-            .filter_map(|field| {
-                //if field.is_increments() {
-                //return None;
-                //}
+    /// Builds the `$n` placeholders of a single row INSERT
+    fn build_values_placeholders(props: &Props) -> String {
+        Self::build_values_templates(props)
+            .iter()
+            .enumerate()
+            .map(|(i, template)| template.replace("{}", &(i + 1).to_string()))
+            .collect::<Vec<String>>()
+            .join(",")
+    }
 
-                current_index += 1;
+    /// Builds the `ON CONFLICT` clause of upserts, taking the new values from `EXCLUDED`
+    fn build_on_conflict(props: &Props) -> String {
+        let table_name = props.get_table_name();
+        let primary_key_ident = props
+            .get_primary_key_field()
+            .unwrap()
+            .ident
+            .as_ref()
+            .unwrap();
 
-                if field.parse_primary_key().is_some() {
-                    return None;
+        let mut sets: Vec<String> = props
+            .get_fields_plain()
+            .iter()
+            .filter(|field| !field.is_version() && !field.is_created_at())
+            .map(|field| {
+                let ident = field.ident.as_ref().unwrap();
+                match field.is_updated_at() {
+                    true => format!("{} = now()", ident),
+                    false => format!("{0} = EXCLUDED.{0}", ident),
                 }
-
-                let ident = &field.ident;
-                let v = format!("${}", current_index);
-                let comma = match comma_index {
-                    0 => quote! {},
-                    _ => quote! {,},
-                };
-                comma_index += 1;
-
-                Some(quote! {
-                    concat!(stringify!(#comma #ident =), #v)
-                })
             })
             .collect();
 
-        let on_conflict_do = match fields_plain_to_set.len() {
-            0 => quote! {"NOTHING"},
-            _ => quote! {"UPDATE SET ", #(#fields_plain_to_set),* },
-        };
+        // the version is bumped by the database and only rows still holding the loaded one are updated
+        let mut version_where = String::new();
+        if let Some(field) = props.get_version_field() {
+            let ident = field.ident.as_ref().unwrap();
+            sets.push(format!("{1} = \"{0}\".{1} + 1", table_name, ident));
+            version_where = format!(" WHERE \"{0}\".{1} = EXCLUDED.{1}", table_name, ident);
+        }
+
+        match sets.is_empty() {
+            true => format!(" ON CONFLICT ({}) DO NOTHING", primary_key_ident),
+            false => format!(
+                " ON CONFLICT ({}) DO UPDATE SET {}{}",
+                primary_key_ident,
+                sets.join(", "),
+                version_where
+            ),
+        }
+    }
+
+    /// Builds a multi-row INSERT of `rows_count` entities followed by `suffix`
+    fn build_many_query(props: &Props, suffix: String) -> TokenStream2 {
+        let table_name = props.get_table_name();
+        let fields_ident = props.get_fields_all_names();
+        let templates = Self::build_values_templates(props);
+        let columns_count = templates.len();
+        let indexes: Vec<usize> = (1..=columns_count).collect();
+
+        let primary_key_ident = &props.get_primary_key_field().unwrap().ident;
+        let returning = Self::build_returning_columns(props);
+
+        quote! {
+            let rows_values: Vec<String> = (0..rows_count)
+                .map(|row| {
+                    let offset = row * #columns_count;
+                    let values: Vec<String> = vec![#( format!(#templates, offset + #indexes) ),*];
+                    format!("({})", values.join(","))
+                })
+                .collect();
+            let query = format!(
+                "INSERT INTO \"{}\" ({}) values {}{} RETURNING {}{};",
+                #table_name,
+                stringify!(#(#fields_ident),*),
+                rows_values.join(","),
+                #suffix,
+                stringify!(#primary_key_ident),
+                #returning
+            );
+        }
+    }
+}
+
+impl Builder for PostgresBuilder {
+    fn new() -> Self {
+        PostgresBuilder {}
+    }
+
+    fn build_save_query(props: &crate::props::Props) -> TokenStream2 {
+        let table_name = props.get_table_name();
+
+        let fields_ident: Vec<&Option<syn::Ident>> =
+            props.get_fields_all().map(|field| &field.ident).collect();
+        let fields_query_values = Self::build_values_placeholders(props);
+        let on_conflict = Self::build_on_conflict(props);
 
         let primary_key = props.get_primary_key_field().unwrap();
         let primary_key_ident = &primary_key.ident;
+        let returning = Self::build_returning_columns(props);
+
+        quote! {
+            let query = concat!("INSERT INTO \"", #table_name, "\"",
+                " (", stringify!(#(#fields_ident),*),
+                ") values (", #fields_query_values,
+                ")", #on_conflict,
+                " RETURNING ", stringify!(#primary_key_ident), #returning, ";"
+            );
+        }
+    }
+
+    fn build_insert_many_query(props: &Props) -> TokenStream2 {
+        Self::build_many_query(props, String::new())
+    }
+
+    fn build_upsert_many_query(props: &Props) -> TokenStream2 {
+        Self::build_many_query(props, Self::build_on_conflict(props))
+    }
+
+    fn build_insert_query(props: &Props) -> TokenStream2 {
+        let table_name = props.get_table_name();
+
+        let fields_ident = props.get_fields_all_names();
+        let fields_query_values = Self::build_values_placeholders(props);
+
+        let primary_key_ident = &props.get_primary_key_field().unwrap().ident;
+        let returning = Self::build_returning_columns(props);
 
         quote! {
             let query = concat!("INSERT INTO \"", #table_name, "\"",
                 " (", stringify!(#(#fields_ident),*),
                 ") values (", #fields_query_values,
-                ") ON CONFLICT (", stringify!(#primary_key_ident), ") DO ", #on_conflict_do,
-                " RETURNING ", stringify!(#primary_key_ident), ";"
+                ") ON CONFLICT (", stringify!(#primary_key_ident), ") DO NOTHING",
+                " RETURNING ", stringify!(#primary_key_ident), #returning, ";"
+            );
+        }
+    }
+
+    fn build_update_changed_query(props: &Props) -> TokenStream2 {
+        let table_name = props.get_table_name();
+        let primary_key_ident = &props.get_primary_key_field().unwrap().ident;
+
+        let returning = Self::build_returning_columns(props);
+        let (version_set, version_where) = match props.get_version_field() {
+            Some(field) => {
+                let ident = field.ident.as_ref().unwrap();
+                (
+                    quote! { sets.push(format!("{0} = {0} + 1", stringify!(#ident))); },
+                    quote! { format!(" AND {} = ${}", stringify!(#ident), changed_columns.len() + 2) },
+                )
+            }
+            None => (quote! {}, quote! { "" }),
+        };
+        let updated_at_set = match props.get_fields_all().find(|field| field.is_updated_at()) {
+            Some(field) => {
+                let ident = &field.ident;
+                quote! { sets.push(format!("{} = now()", stringify!(#ident))); }
+            }
+            None => quote! {},
+        };
+
+        quote! {
+            let mut sets: Vec<String> = changed_columns
+                .iter()
+                .enumerate()
+                .map(|(i, column)| format!("{} = ${}", column, i + 1))
+                .collect();
+            #version_set
+            #updated_at_set
+            if sets.is_empty() {
+                // entities without plain columns only check that the row exists
+                sets.push(format!("{0} = {0}", stringify!(#primary_key_ident)));
+            }
+            let query = format!(
+                "UPDATE \"{}\" SET {} WHERE {} = ${}{} RETURNING {}{}",
+                #table_name,
+                sets.join(", "),
+                stringify!(#primary_key_ident),
+                changed_columns.len() + 1,
+                #version_where,
+                stringify!(#primary_key_ident),
+                #returning
             );
         }
     }
 
     fn build_find_query(props: &Props) -> TokenStream2 {
         let table_name = props.get_table_name();
+        let scope = Self::build_scope(props);
         quote! {
+            #scope
             let query = format!("SELECT * FROM \"{}\" WHERE {}", #table_name, condition)
         }
     }
 
     fn build_first_query(props: &Props) -> TokenStream2 {
         let table_name = props.get_table_name();
+        let scope = Self::build_scope(props);
         quote! {
+            #scope
             let query = format!("SELECT * FROM \"{}\" WHERE {} LIMIT 1", #table_name, condition);
         }
     }
@@ -124,13 +291,215 @@ impl Builder for PostgresBuilder {
         }
     }
 
-    fn build_relation_get_query(props: &Props, relation: &RelationAttr) -> TokenStream2 {
-        let model = format_ident!("{}", relation.model);
+    fn build_soft_delete_query(props: &Props) -> TokenStream2 {
+        let primary_key_ident = &props.get_primary_key_field().unwrap().ident;
+        let soft_delete_ident = &props.get_soft_delete_field().unwrap().ident;
+        let table_name = props.get_table_name();
+        quote! {
+            let query = format!(
+                "UPDATE \"{0}\" SET {1} = now() WHERE {2} = $1 AND {1} IS NULL RETURNING {1}",
+                #table_name,
+                stringify!(#soft_delete_ident),
+                stringify!(#primary_key_ident)
+            );
+        }
+    }
+
+    fn build_restore_query(props: &Props) -> TokenStream2 {
+        let primary_key_ident = &props.get_primary_key_field().unwrap().ident;
+        let soft_delete_ident = &props.get_soft_delete_field().unwrap().ident;
+        let table_name = props.get_table_name();
+        quote! {
+            let query = format!(
+                "UPDATE \"{0}\" SET {1} = NULL WHERE {2} = $1 AND {1} IS NOT NULL",
+                #table_name,
+                stringify!(#soft_delete_ident),
+                stringify!(#primary_key_ident)
+            );
+        }
+    }
+
+    fn build_update_where_query(props: &Props) -> TokenStream2 {
+        let table_name = props.get_table_name();
+        let scope = Self::build_scope(props);
+
+        let mut extra_sets = String::new();
+        if let Some(field) = props.get_version_field() {
+            extra_sets += &format!(", {0} = {0} + 1", field.ident.as_ref().unwrap());
+        }
+        if let Some(field) = props.get_fields_all().find(|field| field.is_updated_at()) {
+            extra_sets += &format!(", {} = now()", field.ident.as_ref().unwrap());
+        }
+
+        quote! {
+            #scope
+            let query = format!(
+                "UPDATE \"{}\" SET {}{} WHERE {}{}",
+                #table_name,
+                assignments,
+                #extra_sets,
+                condition,
+                returning
+            );
+        }
+    }
+
+    fn build_delete_where_query(props: &Props) -> TokenStream2 {
+        let table_name = props.get_table_name();
+        match props.get_soft_delete_field() {
+            Some(field) => {
+                let soft_delete_ident = &field.ident;
+                quote! {
+                    let query = format!(
+                        "UPDATE \"{0}\" SET {1} = now() WHERE {1} IS NULL AND ({2}){3}",
+                        #table_name,
+                        stringify!(#soft_delete_ident),
+                        condition,
+                        returning
+                    );
+                }
+            }
+            None => quote! {
+                let query = format!("DELETE FROM \"{}\" WHERE {}{}", #table_name, condition, returning);
+            },
+        }
+    }
+
+    fn build_copy_out_query(props: &Props) -> TokenStream2 {
+        let table_name = props.get_table_name();
+        let columns = props
+            .get_fields_all()
+            .map(|field| field.ident.as_ref().unwrap().to_string())
+            .collect::<Vec<String>>()
+            .join(", ");
+        let scope = Self::build_scope(props);
+        quote! {
+            let condition = "TRUE";
+            #scope
+            let query = format!("SELECT {} FROM \"{}\" WHERE {}", #columns, #table_name, condition);
+        }
+    }
+
+    fn build_count_query(props: &Props) -> TokenStream2 {
+        let table_name = props.get_table_name();
+        let scope = Self::build_scope(props);
+        quote! {
+            #scope
+            let query = format!("SELECT COUNT(*) FROM \"{}\" WHERE {}", #table_name, condition);
+        }
+    }
+
+    fn build_exists_query(props: &Props) -> TokenStream2 {
+        let table_name = props.get_table_name();
+        let scope = Self::build_scope(props);
+        quote! {
+            #scope
+            let query = format!("SELECT EXISTS(SELECT 1 FROM \"{}\" WHERE {})", #table_name, condition);
+        }
+    }
+
+    fn build_aggregate_query(props: &Props) -> TokenStream2 {
+        let table_name = props.get_table_name();
+        let scope = Self::build_scope(props);
+        quote! {
+            #scope
+            let query = format!("SELECT {} FROM \"{}\" WHERE {}", aggregate, #table_name, condition);
+        }
+    }
+
+    fn build_group_by_query(props: &Props) -> TokenStream2 {
+        let table_name = props.get_table_name();
+        let scope = Self::build_scope(props);
+        quote! {
+            #scope
+            let query = format!(
+                "SELECT {}, {} FROM \"{}\" WHERE {} GROUP BY {} ORDER BY {}",
+                columns, aggregates, #table_name, condition, columns, columns
+            );
+        }
+    }
+
+    fn build_partial_find_query(props: &Props, entity: &Path) -> TokenStream2 {
+        let columns = props.get_fields_all_names();
+        quote! {
+            // the soft delete field belongs to the entity, the partial may not select it
+            let condition = match <#entity>::get_soft_delete_name() {
+                Some(column) => format!("{} IS NULL AND ({})", column, condition),
+                None => condition.to_string(),
+            };
+            let query = format!(
+                "SELECT {} FROM \"{}\" WHERE {}",
+                stringify!(#(#columns),*),
+                <#entity>::get_table_name(),
+                condition
+            );
+        }
+    }
+
+    fn build_partial_first_query(props: &Props, entity: &Path) -> TokenStream2 {
+        let columns = props.get_fields_all_names();
+        quote! {
+            // the soft delete field belongs to the entity, the partial may not select it
+            let condition = match <#entity>::get_soft_delete_name() {
+                Some(column) => format!("{} IS NULL AND ({})", column, condition),
+                None => condition.to_string(),
+            };
+            let query = format!(
+                "SELECT {} FROM \"{}\" WHERE {} LIMIT 1",
+                stringify!(#(#columns),*),
+                <#entity>::get_table_name(),
+                condition
+            );
+        }
+    }
+
+    fn build_relation_get_condition(_props: &Props, relation: &RelationAttr) -> TokenStream2 {
         let key = format_ident!("{}", relation.key);
 
         quote! {
-            let table_name = <#model>::get_table_name();
-            let query = format!("select * from \"{}\" where {} = $1 limit 1", &table_name, stringify!(#key));
+            let condition = format!("{} = $1", stringify!(#key));
+        }
+    }
+
+    fn build_relation_ancestors_query(props: &Props, field: &Field) -> TokenStream2 {
+        let table_name = props.get_table_name();
+        let primary_key_ident = &props.get_primary_key_field().unwrap().ident;
+        let local_key = &field.ident;
+        let key = format_ident!("{}", field.parse_relation().unwrap().key);
+        let scope = Self::build_tree_scope(props, "t");
+
+        quote! {
+            let query = concat!(
+                "WITH RECURSIVE \"__ancestors\" AS (",
+                "SELECT t.*, 1 AS \"__depth\", ARRAY[t.", stringify!(#primary_key_ident), "] AS \"__path\"",
+                " FROM \"", #table_name, "\" t WHERE t.", stringify!(#key), " = $1", #scope,
+                " UNION ALL ",
+                "SELECT t.*, a.\"__depth\" + 1, a.\"__path\" || t.", stringify!(#primary_key_ident),
+                " FROM \"", #table_name, "\" t INNER JOIN \"__ancestors\" a ON t.", stringify!(#key), " = a.", stringify!(#local_key),
+                " WHERE NOT t.", stringify!(#primary_key_ident), " = ANY(a.\"__path\")", #scope,
+                ") SELECT * FROM \"__ancestors\" ORDER BY \"__depth\""
+            );
+        }
+    }
+
+    fn build_relation_descendants_query(props: &Props, field: &Field) -> TokenStream2 {
+        let table_name = props.get_table_name();
+        let primary_key_ident = &props.get_primary_key_field().unwrap().ident;
+        let local_key = &field.ident;
+        let key = format_ident!("{}", field.parse_relation().unwrap().key);
+        let scope = Self::build_tree_scope(props, "t");
+
+        quote! {
+            let query = concat!(
+                "WITH RECURSIVE \"__descendants\" AS (",
+                "SELECT t.*, 1 AS \"__depth\", ARRAY[t.", stringify!(#primary_key_ident), "] AS \"__path\"",
+                " FROM \"", #table_name, "\" t WHERE t.", stringify!(#local_key), " = $1 AND ($2::int4 IS NULL OR $2 > 0)", #scope,
+                " UNION ALL ",
+                "SELECT t.*, d.\"__depth\" + 1, d.\"__path\" || t.", stringify!(#primary_key_ident),
+                " FROM \"", #table_name, "\" t INNER JOIN \"__descendants\" d ON t.", stringify!(#local_key), " = d.", stringify!(#key),
+                " WHERE ($2::int4 IS NULL OR d.\"__depth\" < $2) AND NOT t.", stringify!(#primary_key_ident), " = ANY(d.\"__path\")", #scope,
+                ") SELECT * FROM \"__descendants\" ORDER BY \"__depth\""
+            );
         }
     }
 
@@ -141,4 +510,14 @@ impl Builder for PostgresBuilder {
             let query = format!("{} = $1", #field);
         }
     }
+
+    fn build_relation_polymorphic_get_condition(
+        _props: &Props,
+        _attr: &PolymorphicAttr,
+        model: &Ident,
+    ) -> TokenStream2 {
+        quote! {
+            let query = format!("{} = $1", <#model>::get_primary_key_name());
+        }
+    }
 }
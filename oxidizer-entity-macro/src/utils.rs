@@ -96,6 +96,23 @@ pub fn is_chrono_option(segment: &PathSegment) -> bool {
     is_typed_with(segment, expected) || is_typed_with(segment, no_option_expected)
 }
 
+/// Type wrapped by `Option` in `ty`, `ty` itself when it is not optional
+pub fn option_inner_type(ty: &Type) -> &Type {
+    if let Type::Path(TypePath { path, .. }) = ty {
+        if let Some(segment) = path.segments.last() {
+            if let PathArguments::AngleBracketed(args) = &segment.arguments {
+                if segment.ident == "Option" {
+                    if let Some(GenericArgument::Type(inner)) = args.args.first() {
+                        return inner;
+                    }
+                }
+            }
+        }
+    }
+
+    ty
+}
+
 pub fn search_attr_in_field(field: &Field, attr: &str) -> bool {
     for option in (&field.attrs).into_iter() {
         let option = option.parse_meta().unwrap();
@@ -0,0 +1,189 @@
+//!
+//! # Audit history
+//!
+//! Entities declared with `#[entity(audited)]` get a `<table>_history` table and a trigger in
+//! their `create_migration()`. Every insert, update and delete of a row, through the generated
+//! methods or plain SQL, appends an entry with the operation, json snapshots of the row before
+//! and after the change, the time of the change and the actor set with [set_actor].
+//!
+//! ```
+//! use oxidizer::*;
+//! use oxidizer::audit;
+//!
+//! #[derive(Entity)]
+//! #[entity(audited)]
+//! pub struct Account {
+//!     #[primary_key(increments)]
+//!     id: i32,
+//!     balance: i64,
+//! }
+//!
+//! async fn deposit(db: &DB, account: &mut Account, user: &str) -> DBResult<()> {
+//!     let tx = db.transaction().await?;
+//!     audit::set_actor(&tx, user).await?;
+//!     account.balance += 100;
+//!     account.save(&tx).await?;
+//!     tx.commit().await?;
+//!
+//!     for entry in account.history(db).await? {
+//!         println!("{:?} by {:?} at {}", entry.operation, entry.actor, entry.changed_at);
+//!     }
+//!
+//!     Ok(())
+//! }
+//! ```
+//!
+
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+use tokio_postgres::types::ToSql;
+
+use super::db::{DBResult, Error, DB};
+use super::entity::IEntity;
+use super::migration::Migration;
+
+/// Operation recorded by a [HistoryEntry]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Operation {
+    Insert,
+    Update,
+    Delete,
+}
+
+/// A change of an audited row
+#[derive(Debug)]
+pub struct HistoryEntry<T> {
+    pub id: i32,
+    pub operation: Operation,
+    /// The row before an update or delete
+    pub before: Option<T>,
+    /// The row after an insert or update
+    pub after: Option<T>,
+    /// Actor set with [set_actor] in the transaction of the change
+    pub actor: Option<String>,
+    pub changed_at: DateTime<Utc>,
+}
+
+/// Name of the history table of `table`
+pub fn history_table(table: &str) -> String {
+    format!("{}_history", table)
+}
+
+/// Adds the history table of `table`, keyed by its primary key of type `key_type`, and the
+/// auditing trigger to the migration of entities declared with `#[entity(audited)]`
+pub fn add_history(
+    m: &mut Migration,
+    table: &str,
+    primary_key: &str,
+    key_type: barrel::types::Type,
+) {
+    let history = history_table(table);
+    let index = format!("{}_row_key", history);
+
+    m.raw.create_table(history.as_str(), move |t| {
+        t.add_column("id", barrel::types::custom("SERIAL").primary(true));
+        t.add_column("row_key", key_type.clone().nullable(false));
+        t.add_column("operation", barrel::types::text());
+        t.add_column("before", barrel::types::custom("jsonb").nullable(true));
+        t.add_column("after", barrel::types::custom("jsonb").nullable(true));
+        t.add_column("actor", barrel::types::text().nullable(true));
+        t.add_column(
+            "changed_at",
+            barrel::types::custom("timestamp with time zone DEFAULT now()"),
+        );
+        t.add_index(index.as_str(), barrel::types::index(vec!["row_key"]));
+    });
+
+    m.statements.push(format!(
+        "CREATE OR REPLACE FUNCTION \"{table}_audit\"() RETURNS trigger AS $$
+DECLARE
+    actor TEXT := NULLIF(current_setting('oxidizer.actor', true), '');
+BEGIN
+    IF TG_OP = 'INSERT' THEN
+        INSERT INTO \"{history}\" (row_key, operation, before, after, actor) VALUES (NEW.\"{pk}\", TG_OP, NULL, to_jsonb(NEW), actor);
+    ELSIF TG_OP = 'UPDATE' THEN
+        INSERT INTO \"{history}\" (row_key, operation, before, after, actor) VALUES (NEW.\"{pk}\", TG_OP, to_jsonb(OLD), to_jsonb(NEW), actor);
+    ELSE
+        INSERT INTO \"{history}\" (row_key, operation, before, after, actor) VALUES (OLD.\"{pk}\", TG_OP, to_jsonb(OLD), NULL, actor);
+    END IF;
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql",
+        table = table,
+        history = history_table(table),
+        pk = primary_key,
+    ));
+    m.statements.push(format!(
+        "CREATE TRIGGER \"{table}_audit\" AFTER INSERT OR UPDATE OR DELETE ON \"{table}\" FOR EACH ROW EXECUTE PROCEDURE \"{table}_audit\"()",
+        table = table,
+    ));
+}
+
+/// Records `actor` as the author of the changes made by the rest of the transaction
+pub async fn set_actor(db: &DB, actor: &str) -> DBResult<()> {
+    if !db.is_transaction() {
+        return Err(Error::Other(
+            "The audit actor can only be set in a transaction".to_string(),
+        ));
+    }
+
+    db.query("SELECT set_config('oxidizer.actor', $1, true)", &[&actor])
+        .await?;
+
+    Ok(())
+}
+
+/// Snapshots of the history of `T` decoded by Postgres into rows of its table, by entry id
+async fn snapshots<T: IEntity>(
+    db: &DB,
+    column: &str,
+    key: &(dyn ToSql + Sync),
+) -> DBResult<HashMap<i32, T>> {
+    let table = T::get_table_name();
+    let query = format!(
+        "SELECT h.id AS oxidizer_history_id, r.* FROM \"{history}\" h, jsonb_populate_record(NULL::\"{table}\", h.{column}) r WHERE h.row_key = $1 AND h.{column} IS NOT NULL",
+        history = history_table(&table),
+        table = table,
+        column = column,
+    );
+
+    let rows = db.query(&query, &[key]).await?;
+    let ids = rows.iter().map(|row| row.get("oxidizer_history_id"));
+
+    Ok(ids.zip(T::load_rows(db, &rows).await?).collect())
+}
+
+/// History of the row of `T` with the primary key `key`, oldest first. Also available on the
+/// entities as `entity.history(&db)`.
+pub async fn history<T: IEntity>(db: &DB, key: &T::PrimaryKey) -> DBResult<Vec<HistoryEntry<T>>> {
+    let query = format!(
+        "SELECT id, operation, actor, changed_at FROM \"{}\" WHERE row_key = $1 ORDER BY id",
+        history_table(&T::get_table_name())
+    );
+    let rows = db.query(&query, &[key]).await?;
+
+    let mut before = snapshots::<T>(db, "before", key).await?;
+    let mut after = snapshots::<T>(db, "after", key).await?;
+
+    let mut entries = Vec::with_capacity(rows.len());
+    for row in rows.iter() {
+        let id: i32 = row.get("id");
+        let operation = match row.get::<&str, &str>("operation") {
+            "INSERT" => Operation::Insert,
+            "UPDATE" => Operation::Update,
+            "DELETE" => Operation::Delete,
+            op => return Err(Error::Other(format!("Unknown audited operation {}", op))),
+        };
+
+        entries.push(HistoryEntry {
+            id,
+            operation,
+            before: before.remove(&id),
+            after: after.remove(&id),
+            actor: row.get("actor"),
+            changed_at: row.get("changed_at"),
+        });
+    }
+
+    Ok(entries)
+}
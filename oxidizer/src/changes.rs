@@ -0,0 +1,166 @@
+//!
+//! # Change events
+//!
+//! Entities declared with `#[entity(emit_changes)]` get an `AFTER INSERT OR UPDATE OR DELETE`
+//! trigger in their `create_migration()`, notifying the `<table>_changes` channel of every
+//! changed row. `MyEntity::changes(&db)` subscribes to that channel and yields typed [Change]
+//! events.
+//!
+//! ```
+//! use futures::TryStreamExt;
+//! use oxidizer::*;
+//! use oxidizer::changes::Change;
+//!
+//! #[derive(Entity)]
+//! #[entity(emit_changes)]
+//! pub struct Product {
+//!     #[primary_key(increments)]
+//!     id: i32,
+//!     name: String,
+//! }
+//!
+//! async fn invalidate_cache(db: &DB) -> DBResult<()> {
+//!     let mut changes = Product::changes(db).await?;
+//!
+//!     while let Some(change) = changes.try_next().await? {
+//!         match change {
+//!             Change::Inserted { key, .. } => println!("product {} created", key),
+//!             Change::Updated { key, row } => println!("product {} renamed to {:?}", key, row.map(|p| p.name)),
+//!             Change::Deleted { key, .. } => println!("product {} deleted", key),
+//!         }
+//!     }
+//!
+//!     Ok(())
+//! }
+//! ```
+//!
+//! Notification payloads are limited to 8000 bytes by Postgres, rows too large to fit are sent
+//! without their values and the events only carry their primary key.
+//!
+
+use futures::StreamExt;
+use tokio_postgres::types::FromSql;
+use tokio_postgres::Notification;
+
+use super::db::{DBResult, DBStream, Error, DB};
+use super::entity::IEntity;
+
+/// A row inserted, updated or deleted
+#[derive(Debug)]
+pub enum Change<T: IEntity> {
+    Inserted { key: T::PrimaryKey, row: Option<T> },
+    Updated { key: T::PrimaryKey, row: Option<T> },
+    Deleted { key: T::PrimaryKey, row: Option<T> },
+}
+
+impl<T: IEntity> Change<T> {
+    /// Primary key of the changed row
+    pub fn key(&self) -> &T::PrimaryKey {
+        match self {
+            Change::Inserted { key, .. }
+            | Change::Updated { key, .. }
+            | Change::Deleted { key, .. } => key,
+        }
+    }
+
+    /// Values of the row after an insert or update, before a delete
+    pub fn row(&self) -> Option<&T> {
+        match self {
+            Change::Inserted { row, .. }
+            | Change::Updated { row, .. }
+            | Change::Deleted { row, .. } => row.as_ref(),
+        }
+    }
+}
+
+/// Channel notified of the changes of `table`
+pub fn channel(table: &str) -> String {
+    format!("{}_changes", table)
+}
+
+/// Statements creating the notifying function and trigger of `table`, added to the migration of
+/// entities declared with `#[entity(emit_changes)]`
+pub fn create_trigger_statements(table: &str, primary_key: &str) -> Vec<String> {
+    let function = format!(
+        "CREATE OR REPLACE FUNCTION \"{table}_emit_changes\"() RETURNS trigger AS $$
+DECLARE
+    changed RECORD;
+    payload TEXT;
+BEGIN
+    IF TG_OP = 'DELETE' THEN
+        changed := OLD;
+    ELSE
+        changed := NEW;
+    END IF;
+    payload := json_build_object('op', TG_OP, 'key', json_build_object('{pk}', changed.\"{pk}\"), 'row', row_to_json(changed))::text;
+    IF octet_length(payload) >= 8000 THEN
+        payload := json_build_object('op', TG_OP, 'key', json_build_object('{pk}', changed.\"{pk}\"), 'row', NULL)::text;
+    END IF;
+    PERFORM pg_notify('{channel}', payload);
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql",
+        table = table,
+        pk = primary_key,
+        channel = channel(table).replace('\'', "''"),
+    );
+
+    let trigger = format!(
+        "CREATE TRIGGER \"{table}_emit_changes\" AFTER INSERT OR UPDATE OR DELETE ON \"{table}\" FOR EACH ROW EXECUTE PROCEDURE \"{table}_emit_changes\"()",
+        table = table,
+    );
+
+    vec![function, trigger]
+}
+
+/// Subscribes to the changes of `T`, see `MyEntity::changes`
+pub async fn listen<T>(db: &DB) -> DBResult<DBStream<Change<T>>>
+where
+    T: IEntity + Send + 'static,
+    T::PrimaryKey: for<'a> FromSql<'a> + Send,
+{
+    let notifications = db.listen(&channel(&T::get_table_name())).await?;
+
+    let db = db.clone();
+    let changes = notifications.then(move |notification| decode::<T>(db.clone(), notification));
+
+    Ok(Box::pin(changes))
+}
+
+/// Decodes a notification of the trigger, the row is rebuilt by Postgres from its json
+async fn decode<T>(db: DB, notification: Notification) -> DBResult<Change<T>>
+where
+    T: IEntity + Send,
+    T::PrimaryKey: for<'a> FromSql<'a>,
+{
+    let table = T::get_table_name();
+    let query = format!(
+        "SELECT p->>'op', (json_populate_record(NULL::\"{table}\", p->'key')).\"{pk}\", json_typeof(p->'row') = 'object' FROM (SELECT $1::text::json AS p) AS n",
+        table = table,
+        pk = T::get_primary_key_name(),
+    );
+    let payload = notification.payload();
+    let rows = db.query(&query, &[&payload]).await?;
+    let op: String = rows[0].get(0);
+    let key: T::PrimaryKey = rows[0].get(1);
+    let has_row: bool = rows[0].get(2);
+
+    let row = match has_row {
+        true => {
+            let query = format!(
+                "SELECT * FROM json_populate_record(NULL::\"{}\", $1::text::json->'row')",
+                table
+            );
+            let rows = db.query(&query, &[&payload]).await?;
+            T::load_rows(&db, &rows).await?.pop()
+        }
+        false => None,
+    };
+
+    match op.as_str() {
+        "INSERT" => Ok(Change::Inserted { key, row }),
+        "UPDATE" => Ok(Change::Updated { key, row }),
+        "DELETE" => Ok(Change::Deleted { key, row }),
+        _ => Err(Error::Other(format!("Unknown change operation {}", op))),
+    }
+}
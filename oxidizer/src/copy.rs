@@ -0,0 +1,115 @@
+//!
+//! # Binary COPY
+//!
+//! `copy_in` and `copy_out` move entities in bulk through `COPY ... (FORMAT binary)`, which is
+//! much faster than `insert_many` for loading or exporting large tables.
+//!
+//! `copy_in` validates every entity but does not run hooks, does not write anything back to the
+//! entities and does not check conflicts. Incrementing primary keys and `#[created_at]`/`#[updated_at]`
+//! columns are left out of the copy so the database fills them.
+//!
+//! `copy_out` streams every (non trashed) row of the table back as entities. It does not run the
+//! `after_load` hook either, the connection being busy with the copy until the stream ends.
+//!
+//! ```
+//! use oxidizer::*;
+//! use futures::StreamExt;
+//!
+//! #[derive(Entity, Default)]
+//! pub struct Reading {
+//!     #[primary_key(increments)]
+//!     id: i32,
+//!     sensor: String,
+//!     value: f64,
+//! }
+//!
+//! async fn reload(db: &DB, readings: Vec<Reading>) -> DBResult<()> {
+//!     let copied = Reading::copy_in(db, readings).await?;
+//!
+//!     let mut stream = Reading::copy_out(db).await?;
+//!     while let Some(reading) = stream.next().await {
+//!         let reading = reading?;
+//!     }
+//!     Ok(())
+//! }
+//! ```
+//!
+
+use std::marker::PhantomData;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use bytes::Bytes;
+use futures::{Stream, StreamExt};
+use tokio_postgres::binary_copy::{BinaryCopyInWriter, BinaryCopyOutStream};
+use tokio_postgres::types::{ToSql, Type};
+use tokio_postgres::{CopyInSink, CopyOutStream as RawCopyOutStream};
+
+use super::db::db::ClientHandle;
+use super::db::{DBResult, Error};
+use super::entity::IEntity;
+
+/// Rows being written by a binary `COPY ... FROM STDIN`. The pooled connection is held until
+/// the copy is finished.
+pub struct CopyInWriter {
+    writer: Pin<Box<BinaryCopyInWriter>>,
+    _client: ClientHandle,
+}
+
+impl CopyInWriter {
+    pub(crate) fn new(client: ClientHandle, sink: CopyInSink<Bytes>, types: &[Type]) -> Self {
+        CopyInWriter {
+            writer: Box::pin(BinaryCopyInWriter::new(sink, types)),
+            _client: client,
+        }
+    }
+
+    /// Writes a row, `values` must follow the order of the copied columns
+    pub async fn write(&mut self, values: &[&(dyn ToSql + Sync)]) -> DBResult<()> {
+        self.writer
+            .as_mut()
+            .write(values)
+            .await
+            .map_err(Error::PostgresError)
+    }
+
+    /// Completes the copy, returning the number of rows written
+    pub async fn finish(mut self) -> DBResult<u64> {
+        self.writer
+            .as_mut()
+            .finish()
+            .await
+            .map_err(Error::PostgresError)
+    }
+}
+
+/// Entities decoded from a binary `COPY ... TO STDOUT`. The pooled connection is held until
+/// the stream is dropped.
+pub struct CopyOutStream<T> {
+    rows: Pin<Box<BinaryCopyOutStream>>,
+    _client: ClientHandle,
+    entity: PhantomData<fn() -> T>,
+}
+
+impl<T> CopyOutStream<T> {
+    pub(crate) fn new(client: ClientHandle, stream: RawCopyOutStream, types: &[Type]) -> Self {
+        CopyOutStream {
+            rows: Box::pin(BinaryCopyOutStream::new(stream, types)),
+            _client: client,
+            entity: PhantomData,
+        }
+    }
+}
+
+impl<T: IEntity> Stream for CopyOutStream<T> {
+    type Item = DBResult<T>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        match self.rows.poll_next_unpin(cx) {
+            Poll::Ready(Some(Ok(row))) => Poll::Ready(Some(T::from_copy_row(&row))),
+            Poll::Ready(Some(Err(e))) => Poll::Ready(Some(Err(Error::PostgresError(e)))),
+            Poll::Ready(None) => Poll::Ready(None),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
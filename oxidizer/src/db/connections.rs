@@ -1,11 +1,41 @@
 use async_trait::async_trait;
-use tokio_postgres::{Client, Config, NoTls};
+use futures::channel::mpsc::{self, UnboundedReceiver};
+use futures::{stream, StreamExt};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_postgres::{AsyncMessage, Client, Config, Connection, NoTls, Notification};
 
 use crate::Error;
 
 #[async_trait]
 pub trait ConnectionProvider: Send + Sync + 'static {
     async fn connect(&self) -> Result<Client, tokio_postgres::Error>;
+
+    /// Connects outside of the pool, receiving the notifications of the `LISTEN`s of the client
+    async fn connect_with_notifications(
+        &self,
+    ) -> Result<(Client, UnboundedReceiver<Notification>), tokio_postgres::Error>;
+}
+
+/// Spawns `conn`, forwarding its notifications until the connection is closed
+fn spawn_with_notifications<S, T>(mut conn: Connection<S, T>) -> UnboundedReceiver<Notification>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    T: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let (sender, receiver) = mpsc::unbounded();
+    let mut messages = stream::poll_fn(move |cx| conn.poll_message(cx));
+
+    mobc::spawn(async move {
+        while let Some(Ok(message)) = messages.next().await {
+            if let AsyncMessage::Notification(notification) = message {
+                if sender.unbounded_send(notification).is_err() {
+                    break;
+                }
+            }
+        }
+    });
+
+    receiver
 }
 
 pub async fn create_connection_provider(
@@ -41,6 +71,13 @@ impl ConnectionProvider for NoTlsConnectionProvider {
         mobc::spawn(conn);
         Ok(client)
     }
+
+    async fn connect_with_notifications(
+        &self,
+    ) -> Result<(Client, UnboundedReceiver<Notification>), tokio_postgres::Error> {
+        let (client, conn) = self.config.connect(NoTls).await?;
+        Ok((client, spawn_with_notifications(conn)))
+    }
 }
 
 fn no_tls(config: Config) -> Box<dyn ConnectionProvider> {
@@ -68,6 +105,13 @@ mod tls_openssl {
             mobc::spawn(conn);
             Ok(client)
         }
+
+        async fn connect_with_notifications(
+            &self,
+        ) -> Result<(Client, UnboundedReceiver<Notification>), tokio_postgres::Error> {
+            let (client, conn) = self.config.connect(self.tls.clone()).await?;
+            Ok((client, spawn_with_notifications(conn)))
+        }
     }
 
     fn sync_build_ssl_connector(ca_file: String) -> Result<SslConnector, Error> {
@@ -115,6 +159,13 @@ mod tls_rustls {
             mobc::spawn(conn);
             Ok(client)
         }
+
+        async fn connect_with_notifications(
+            &self,
+        ) -> Result<(Client, UnboundedReceiver<Notification>), tokio_postgres::Error> {
+            let (client, conn) = self.config.connect(self.tls.clone()).await?;
+            Ok((client, spawn_with_notifications(conn)))
+        }
     }
 
     fn sync_initialise_root_store(ca_file: String) -> Result<RootCertStore, Error> {
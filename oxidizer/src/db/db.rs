@@ -6,16 +6,28 @@ use super::connections;
 use connections::ConnectionProvider;
 
 use refinery::{Report, Runner};
+use std::ops::Deref;
 use std::str::FromStr;
+use std::sync::Arc;
 
+use super::super::copy::{CopyInWriter, CopyOutStream};
+use super::super::entity::IEntity;
 use super::super::migration::Migration;
+use super::super::row::FromRow;
 use super::error::*;
+use super::listen::{self, NotificationStream};
+use super::stream::{self, DBStream};
+use super::transaction::Transaction;
 
-use barrel::backend::Pg;
-use tokio_postgres::{row::Row, types::ToSql, Client};
+use futures::channel::mpsc::UnboundedReceiver;
+use tokio_postgres::{
+    row::Row,
+    types::{ToSql, Type},
+    Client, Notification,
+};
 
-struct ConnectionManager {
-    provider: Box<dyn ConnectionProvider>,
+pub(crate) struct ConnectionManager {
+    provider: Arc<dyn ConnectionProvider>,
 }
 
 #[async_trait]
@@ -33,9 +45,37 @@ impl Manager for ConnectionManager {
     }
 }
 
+/// A connection checked out of the pool, returned to it once dropped
+pub(crate) type PooledClient = mobc::Connection<ConnectionManager>;
+
+/// The connection used by a single operation: checked out of the pool or pinned by a transaction
+pub(crate) enum ClientHandle {
+    Pooled(Box<PooledClient>),
+    Pinned(Arc<PooledClient>),
+}
+
+impl Deref for ClientHandle {
+    type Target = Client;
+
+    fn deref(&self) -> &Client {
+        match self {
+            ClientHandle::Pooled(client) => client,
+            ClientHandle::Pinned(client) => client,
+        }
+    }
+}
+
+/// Number of rows fetched at a time by the cursors of `query_stream` inside a transaction
+const CURSOR_FETCH_SIZE: usize = 500;
+
 #[derive(Clone)]
 pub struct DB {
     pool: Pool<ConnectionManager>,
+    provider: Arc<dyn ConnectionProvider>,
+    /// Connection pinned by a transaction, used instead of the pool
+    pub(super) pinned: Option<Arc<PooledClient>>,
+    /// Number of transactions (and savepoints) opened on the pinned connection
+    depth: usize,
 }
 
 unsafe impl std::marker::Sync for DB {}
@@ -44,14 +84,122 @@ impl DB {
     pub async fn connect(uri: &str, max_open: u64, ca_file: Option<&str>) -> Result<Self, Error> {
         let config = tokio_postgres::Config::from_str(uri).map_err(Error::PostgresError)?;
 
-        let provider = connections::create_connection_provider(config, ca_file).await?;
-        let manager = ConnectionManager { provider };
+        let provider: Arc<dyn ConnectionProvider> =
+            connections::create_connection_provider(config, ca_file)
+                .await?
+                .into();
+        let manager = ConnectionManager {
+            provider: provider.clone(),
+        };
 
         Ok(DB {
             pool: Pool::builder().max_open(max_open).build(manager),
+            provider,
+            pinned: None,
+            depth: 0,
         })
     }
 
+    pub(crate) async fn client(&self) -> Result<ClientHandle, Error> {
+        match &self.pinned {
+            Some(client) => Ok(ClientHandle::Pinned(client.clone())),
+            None => Ok(ClientHandle::Pooled(Box::new(
+                self.pool.get().await.map_err(Error::MobcError)?,
+            ))),
+        }
+    }
+
+    /// Starts a transaction on a connection pinned until it is committed or rolled back.
+    /// The returned [Transaction] dereferences to a `DB` which can be passed to every entity method.
+    /// Starting a transaction from a transaction creates a savepoint.
+    pub async fn transaction(&self) -> Result<Transaction, Error> {
+        let (client, savepoint) = match &self.pinned {
+            Some(client) => (
+                client.clone(),
+                Some(format!("oxidizer_savepoint_{}", self.depth)),
+            ),
+            None => (
+                Arc::new(self.pool.get().await.map_err(Error::MobcError)?),
+                None,
+            ),
+        };
+
+        let begin = match &savepoint {
+            Some(name) => format!("SAVEPOINT {}", name),
+            None => "BEGIN".to_string(),
+        };
+        client
+            .batch_execute(&begin)
+            .await
+            .map_err(Error::PostgresError)?;
+
+        let db = DB {
+            pool: self.pool.clone(),
+            provider: self.provider.clone(),
+            pinned: Some(client),
+            depth: self.depth + 1,
+        };
+
+        Ok(Transaction::new(db, savepoint))
+    }
+
+    /// Opens a connection outside of the pool listening to `channels`
+    pub(crate) async fn connect_listener(
+        &self,
+        channels: &[String],
+    ) -> Result<(Client, UnboundedReceiver<Notification>), Error> {
+        let (client, notifications) = self
+            .provider
+            .connect_with_notifications()
+            .await
+            .map_err(Error::PostgresError)?;
+
+        for channel in channels {
+            let listen = format!("LISTEN \"{}\"", channel.replace('"', "\"\""));
+            client
+                .batch_execute(&listen)
+                .await
+                .map_err(Error::PostgresError)?;
+        }
+
+        Ok((client, notifications))
+    }
+
+    /// Subscribes to `channel` on a dedicated connection, outside of the pool, which reconnects
+    /// automatically when lost. The notifications sent while reconnecting are missed.
+    ///
+    /// ```
+    /// use futures::StreamExt;
+    /// use oxidizer::*;
+    ///
+    /// async fn invalidate_cache(db: &DB) -> DBResult<()> {
+    ///     let mut notifications = db.listen("cache_invalidation").await?;
+    ///
+    ///     while let Some(notification) = notifications.next().await {
+    ///         println!("evicting {}", notification.payload());
+    ///     }
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn listen(&self, channel: &str) -> Result<NotificationStream, Error> {
+        listen::listen(self, channel).await
+    }
+
+    /// Sends `payload` to the listeners of `channel`. Inside a transaction the notification is
+    /// only delivered once it is committed.
+    pub async fn notify(&self, channel: &str, payload: &str) -> Result<(), Error> {
+        self.execute("SELECT pg_notify($1, $2)", &[&channel, &payload])
+            .await?;
+
+        Ok(())
+    }
+
+    /// Whether the queries run inside a transaction
+    pub fn is_transaction(&self) -> bool {
+        self.pinned.is_some()
+    }
+
     pub async fn create(
         &self,
         query: &str,
@@ -65,7 +213,7 @@ impl DB {
         query: &str,
         params: &'_ [&'_ (dyn ToSql + Sync)],
     ) -> Result<u64, Error> {
-        let client = self.pool.get().await.map_err(Error::MobcError)?;
+        let client = self.client().await?;
 
         let insert = client.prepare(query).await.map_err(Error::PostgresError)?;
 
@@ -80,7 +228,7 @@ impl DB {
         query: &str,
         params: &'_ [&'_ (dyn ToSql + Sync)],
     ) -> Result<Vec<Row>, Error> {
-        let client = self.pool.get().await.map_err(Error::MobcError)?;
+        let client = self.client().await?;
 
         let insert = client.prepare(query).await.map_err(Error::PostgresError)?;
 
@@ -90,13 +238,96 @@ impl DB {
             .map_err(Error::PostgresError)
     }
 
+    pub async fn query_as<T: FromRow>(
+        &self,
+        query: &str,
+        params: &'_ [&'_ (dyn ToSql + Sync)],
+    ) -> Result<Vec<T>, Error> {
+        let rows = self.query(query, params).await?;
+
+        rows.iter().map(T::from_row).collect()
+    }
+
+    pub async fn query_one_as<T: FromRow>(
+        &self,
+        query: &str,
+        params: &'_ [&'_ (dyn ToSql + Sync)],
+    ) -> Result<Option<T>, Error> {
+        let rows = self.query(query, params).await?;
+
+        rows.first().map(T::from_row).transpose()
+    }
+
+    /// Runs `query` returning a stream decoding rows as they are received instead of buffering
+    /// them. The connection is held until the stream is dropped. Inside a transaction the rows are
+    /// fetched in batches through a cursor which lives until the end of the transaction.
+    pub async fn query_stream(
+        &self,
+        query: &str,
+        params: &'_ [&'_ (dyn ToSql + Sync)],
+    ) -> Result<DBStream<Row>, Error> {
+        match self.client().await? {
+            ClientHandle::Pinned(client) => {
+                stream::query_cursor(client, query, params, CURSOR_FETCH_SIZE).await
+            }
+            client => stream::query_raw(client, query, params).await,
+        }
+    }
+
+    /// Starts a binary `COPY ... FROM STDIN` of `columns` into `table`. The column types are
+    /// looked up from the table so the rows can be encoded in the binary format.
+    pub async fn copy_in(&self, table: &str, columns: &[&str]) -> Result<CopyInWriter, Error> {
+        let client = self.client().await?;
+
+        let columns = columns.join(", ");
+        let select = format!("SELECT {} FROM \"{}\" LIMIT 0", columns, table);
+        let statement = client
+            .prepare(&select)
+            .await
+            .map_err(Error::PostgresError)?;
+        let types: Vec<Type> = statement
+            .columns()
+            .iter()
+            .map(|c| c.type_().clone())
+            .collect();
+
+        let copy = format!(
+            "COPY \"{}\" ({}) FROM STDIN (FORMAT binary)",
+            table, columns
+        );
+        let sink = client.copy_in(&*copy).await.map_err(Error::PostgresError)?;
+
+        Ok(CopyInWriter::new(client, sink, &types))
+    }
+
+    /// Runs a binary `COPY (query) TO STDOUT` and decodes every row with `T::from_copy_row`.
+    /// `query` can not take parameters.
+    pub async fn copy_out<T: IEntity>(&self, query: &str) -> Result<CopyOutStream<T>, Error> {
+        let client = self.client().await?;
+
+        let statement = client.prepare(query).await.map_err(Error::PostgresError)?;
+        let types: Vec<Type> = statement
+            .columns()
+            .iter()
+            .map(|c| c.type_().clone())
+            .collect();
+
+        let copy = format!("COPY ({}) TO STDOUT (FORMAT binary)", query);
+        let stream = client
+            .copy_out(&*copy)
+            .await
+            .map_err(Error::PostgresError)?;
+
+        Ok(CopyOutStream::new(client, stream, &types))
+    }
+
     pub async fn migrate_tables(&self, ms: &[Migration]) -> Result<Report, Error> {
         let ref_migrations: Vec<refinery::Migration> = ms
             .as_ref()
             .iter()
             .enumerate()
             .filter_map(|(i, m)| {
-                let sql = m.raw.make::<Pg>();
+                let sql = m.make();
 
                 let name = format!("V{}__{}.rs", i, m.name);
 
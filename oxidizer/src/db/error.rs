@@ -9,6 +9,9 @@ pub enum Error {
     MobcError(mobc::Error<tokio_postgres::Error>),
     RefineryError(refinery::Error),
     DoesNotExist,
+    AlreadyExists,
+    StaleEntity,
+    Validation(Vec<crate::validation::FieldError>),
     ReferencedModelIsNotInDB,
     Other(String),
 }
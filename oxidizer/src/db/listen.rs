@@ -0,0 +1,160 @@
+//!
+//! Subscriptions to `LISTEN`/`NOTIFY` channels, see [DB::listen](super::DB::listen)
+//!
+
+use futures::channel::{mpsc, oneshot};
+use futures::future::{self, Either};
+use futures::{FutureExt, Stream, StreamExt};
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use tokio_postgres::{Client, Notification};
+
+use super::db::DB;
+use super::error::Error;
+
+/// Delay before the first reconnection attempt, doubled after each failure
+const RECONNECT_DELAY: Duration = Duration::from_millis(100);
+const RECONNECT_MAX_DELAY: Duration = Duration::from_secs(30);
+
+/// Notifications received on a channel, returned by [DB::listen](super::DB::listen).
+///
+/// The stream owns a dedicated connection, outside of the pool, which is re-established
+/// whenever it is lost. Notifications sent while reconnecting are missed. Dropping the stream
+/// closes the connection.
+pub struct NotificationStream {
+    notifications: mpsc::UnboundedReceiver<Notification>,
+    _stop: oneshot::Sender<()>,
+}
+
+impl Stream for NotificationStream {
+    type Item = Notification;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.notifications.poll_next_unpin(cx)
+    }
+}
+
+pub(crate) async fn listen(db: &DB, channel: &str) -> Result<NotificationStream, Error> {
+    let channels = vec![channel.to_string()];
+    let connection = db.connect_listener(&channels).await?;
+
+    let (sender, notifications) = mpsc::unbounded();
+    let (stop_sender, stop) = oneshot::channel();
+
+    tokio::spawn(forward(db.clone(), channels, connection, sender, stop));
+
+    Ok(NotificationStream {
+        notifications,
+        _stop: stop_sender,
+    })
+}
+
+/// Forwards the notifications of `connection` to `sender`, reconnecting until the stream is
+/// dropped
+async fn forward(
+    db: DB,
+    channels: Vec<String>,
+    connection: (Client, mpsc::UnboundedReceiver<Notification>),
+    sender: mpsc::UnboundedSender<Notification>,
+    mut stop: oneshot::Receiver<()>,
+) {
+    let (mut _client, mut received) = connection;
+
+    loop {
+        loop {
+            match future::select(&mut stop, received.next()).await {
+                Either::Left(_) => return,
+                Either::Right((Some(notification), _)) => {
+                    if sender.unbounded_send(notification).is_err() {
+                        return;
+                    }
+                }
+                Either::Right((None, _)) => break,
+            }
+        }
+
+        let mut delay = RECONNECT_DELAY;
+        loop {
+            match future::select(&mut stop, tokio::time::delay_for(delay)).await {
+                Either::Left(_) => return,
+                Either::Right(_) => {}
+            }
+
+            if let Ok((client, notifications)) = db.connect_listener(&channels).await {
+                _client = client;
+                received = notifications;
+                break;
+            }
+
+            delay = std::cmp::min(delay * 2, RECONNECT_MAX_DELAY);
+        }
+    }
+}
+
+/// Runs `batch` until `shutdown` completes: right away again while it processes rows, otherwise
+/// on a notification of `channel` or after `poll_interval`. Errors are logged under `name` and
+/// `batch` is retried after a delay doubling up to `RECONNECT_MAX_DELAY`.
+pub(crate) async fn run_batches<B, R, S>(
+    db: &DB,
+    channel: &str,
+    poll_interval: Duration,
+    name: &str,
+    mut batch: B,
+    shutdown: S,
+) where
+    B: FnMut() -> R,
+    R: Future<Output = Result<usize, Error>>,
+    S: Future<Output = ()>,
+{
+    let mut shutdown = Box::pin(shutdown);
+    let mut notifications = None;
+    let mut delay = RECONNECT_DELAY;
+
+    loop {
+        if notifications.is_none() {
+            // without notifications the batches are only polled
+            notifications = listen(db, channel).await.ok();
+        }
+
+        match batch().await {
+            Ok(0) => delay = RECONNECT_DELAY,
+            Ok(_) => {
+                delay = RECONNECT_DELAY;
+                if (&mut shutdown).now_or_never().is_some() {
+                    return;
+                }
+                continue;
+            }
+            Err(error) => {
+                eprintln!(
+                    "[ERROR] {} failed, retrying in {:?}: {:?}",
+                    name, delay, error
+                );
+                let retry = tokio::time::delay_for(delay);
+                delay = std::cmp::min(delay * 2, RECONNECT_MAX_DELAY);
+
+                futures::pin_mut!(retry);
+                match future::select(&mut shutdown, retry).await {
+                    Either::Left(_) => return,
+                    Either::Right(_) => continue,
+                }
+            }
+        }
+
+        let wake = async {
+            match &mut notifications {
+                Some(notifications) => {
+                    let _ = tokio::time::timeout(poll_interval, notifications.next()).await;
+                }
+                None => tokio::time::delay_for(poll_interval).await,
+            }
+        };
+        futures::pin_mut!(wake);
+        if let Either::Left(_) = future::select(&mut shutdown, wake).await {
+            return;
+        }
+    }
+}
@@ -3,6 +3,12 @@ pub mod db;
 pub use db::DB;
 pub mod error;
 pub use error::*;
+pub mod listen;
+pub use listen::NotificationStream;
+pub mod stream;
+pub use stream::DBStream;
+pub mod transaction;
+pub use transaction::Transaction;
 pub mod test_utils;
 
 #[cfg(test)]
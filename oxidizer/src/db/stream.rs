@@ -0,0 +1,157 @@
+use futures::stream::{self, Stream, StreamExt};
+use std::collections::VecDeque;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use tokio_postgres::{types::ToSql, Row, RowStream};
+
+use super::super::entity::IEntity;
+use super::super::hooks::EntityHooks;
+use super::db::{ClientHandle, PooledClient, DB};
+use super::error::*;
+
+/// Stream of rows or entities decoded lazily, returned by `DB::query_stream` and `find_stream`
+pub type DBStream<T> = Pin<Box<dyn Stream<Item = DBResult<T>> + Send>>;
+
+static CURSOR_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// Rows of a `query_raw`, holding the connection until the stream is dropped
+struct RawRows {
+    rows: Pin<Box<RowStream>>,
+    _client: ClientHandle,
+}
+
+impl Stream for RawRows {
+    type Item = DBResult<Row>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        match self.rows.poll_next_unpin(cx) {
+            Poll::Ready(Some(Ok(row))) => Poll::Ready(Some(Ok(row))),
+            Poll::Ready(Some(Err(e))) => Poll::Ready(Some(Err(Error::PostgresError(e)))),
+            Poll::Ready(None) => Poll::Ready(None),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+pub(crate) async fn query_raw(
+    client: ClientHandle,
+    query: &str,
+    params: &'_ [&'_ (dyn ToSql + Sync)],
+) -> DBResult<DBStream<Row>> {
+    let statement = client.prepare(query).await.map_err(Error::PostgresError)?;
+    let rows = client
+        .query_raw(&statement, params.iter().map(|p| *p as &dyn ToSql))
+        .await
+        .map_err(Error::PostgresError)?;
+
+    Ok(Box::pin(RawRows {
+        rows: Box::pin(rows),
+        _client: client,
+    }))
+}
+
+struct Cursor {
+    client: Arc<PooledClient>,
+    fetch: String,
+    fetch_size: usize,
+    rows: VecDeque<Row>,
+    done: bool,
+}
+
+/// Declares a cursor for `query` on the connection of a transaction, fetching `fetch_size`
+/// rows at a time as the stream is consumed
+pub(crate) async fn query_cursor(
+    client: Arc<PooledClient>,
+    query: &str,
+    params: &'_ [&'_ (dyn ToSql + Sync)],
+    fetch_size: usize,
+) -> DBResult<DBStream<Row>> {
+    let name = format!(
+        "oxidizer_cursor_{}",
+        CURSOR_COUNT.fetch_add(1, Ordering::Relaxed)
+    );
+    let declare = format!("DECLARE {} NO SCROLL CURSOR FOR {}", name, query);
+    client
+        .execute(declare.as_str(), params)
+        .await
+        .map_err(Error::PostgresError)?;
+
+    let cursor = Cursor {
+        client,
+        fetch: format!("FETCH {} FROM {}", fetch_size, name),
+        fetch_size,
+        rows: VecDeque::new(),
+        done: false,
+    };
+
+    Ok(Box::pin(stream::unfold(cursor, |mut cursor| async move {
+        loop {
+            if let Some(row) = cursor.rows.pop_front() {
+                return Some((Ok(row), cursor));
+            }
+            if cursor.done {
+                return None;
+            }
+
+            match cursor.client.query(cursor.fetch.as_str(), &[]).await {
+                Ok(rows) => {
+                    cursor.done = rows.len() < cursor.fetch_size;
+                    cursor.rows.extend(rows);
+                }
+                Err(e) => {
+                    cursor.done = true;
+                    return Some((Err(Error::PostgresError(e)), cursor));
+                }
+            }
+        }
+    })))
+}
+
+/// Entities decoded from a stream of rows with `from_row`
+struct Entities<T> {
+    rows: DBStream<Row>,
+    entity: std::marker::PhantomData<fn() -> T>,
+}
+
+impl<T: IEntity> Stream for Entities<T> {
+    type Item = DBResult<T>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        match self.rows.poll_next_unpin(cx) {
+            Poll::Ready(Some(Ok(row))) => Poll::Ready(Some(T::from_row(&row))),
+            Poll::Ready(Some(Err(e))) => Poll::Ready(Some(Err(e))),
+            Poll::Ready(None) => Poll::Ready(None),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+/// Decodes every row of `rows` into `T`
+pub fn decode_stream<T>(rows: DBStream<Row>) -> DBStream<T>
+where
+    T: IEntity + Send + 'static,
+{
+    Box::pin(Entities {
+        rows,
+        entity: std::marker::PhantomData,
+    })
+}
+
+/// Decodes every row of `rows` into `T`, running its `after_load` hook
+pub fn load_stream<T>(db: &DB, rows: DBStream<Row>) -> DBStream<T>
+where
+    T: IEntity + EntityHooks + Send + 'static,
+{
+    let db = db.clone();
+    Box::pin(decode_stream::<T>(rows).then(move |entity| {
+        let db = db.clone();
+        async move {
+            let mut entity = entity?;
+            entity.after_load(&db).await?;
+            Ok(entity)
+        }
+    }))
+}
@@ -38,3 +38,126 @@ async fn test_db_raw_query() {
     assert_eq!(1, row.len());
     assert_eq!("abcde", row[0].get::<&str, &str>("code"));
 }
+
+#[tokio::test]
+async fn test_db_transaction() {
+    let db = super::test_utils::create_test_db("test_db_transaction").await;
+
+    db.execute("CREATE TABLE items (name text NOT NULL)", &[])
+        .await
+        .unwrap();
+
+    let tx = db.transaction().await.unwrap();
+    assert!(tx.is_transaction());
+    tx.execute("INSERT INTO items (name) VALUES ($1)", &[&"committed"])
+        .await
+        .unwrap();
+    let rows = db.query("SELECT * FROM items", &[]).await.unwrap();
+    assert_eq!(0, rows.len());
+    tx.commit().await.unwrap();
+
+    let tx = db.transaction().await.unwrap();
+    tx.execute("INSERT INTO items (name) VALUES ($1)", &[&"rolled back"])
+        .await
+        .unwrap();
+    tx.rollback().await.unwrap();
+
+    {
+        let tx = db.transaction().await.unwrap();
+        tx.execute("INSERT INTO items (name) VALUES ($1)", &[&"dropped"])
+            .await
+            .unwrap();
+    }
+
+    let tx = db.transaction().await.unwrap();
+    let savepoint = tx.transaction().await.unwrap();
+    savepoint
+        .execute("INSERT INTO items (name) VALUES ($1)", &[&"savepoint"])
+        .await
+        .unwrap();
+    savepoint.rollback().await.unwrap();
+    tx.execute("INSERT INTO items (name) VALUES ($1)", &[&"outer"])
+        .await
+        .unwrap();
+    tx.commit().await.unwrap();
+
+    let rows = db
+        .query("SELECT name FROM items ORDER BY name", &[])
+        .await
+        .unwrap();
+    let names: Vec<&str> = rows.iter().map(|row| row.get(0)).collect();
+    assert_eq!(vec!["committed", "outer"], names);
+}
+
+#[tokio::test]
+async fn test_db_query_stream() {
+    use futures::TryStreamExt;
+
+    let db = super::test_utils::create_test_db("test_db_query_stream").await;
+
+    let query = "SELECT i FROM generate_series(1, $1) AS i";
+    let rows: Vec<tokio_postgres::Row> = db
+        .query_stream(query, &[&2000])
+        .await
+        .unwrap()
+        .try_collect()
+        .await
+        .unwrap();
+    assert_eq!(2000, rows.len());
+
+    // fetched in batches through a cursor
+    let tx = db.transaction().await.unwrap();
+    let mut stream = tx.query_stream(query, &[&1234]).await.unwrap();
+    let mut sum: i64 = 0;
+    while let Some(row) = stream.try_next().await.unwrap() {
+        sum += row.get::<usize, i32>(0) as i64;
+    }
+    assert_eq!(1234 * 1235 / 2, sum);
+    drop(stream);
+    tx.commit().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_db_listen() {
+    use futures::StreamExt;
+    use std::time::Duration;
+
+    let db = super::test_utils::create_test_db("test_db_listen").await;
+
+    let mut notifications = db.listen("test_db_listen").await.unwrap();
+
+    db.notify("test_db_listen", "first").await.unwrap();
+    let notification = notifications.next().await.unwrap();
+    assert_eq!("test_db_listen", notification.channel());
+    assert_eq!("first", notification.payload());
+
+    // delivered on commit only
+    let tx = db.transaction().await.unwrap();
+    tx.notify("test_db_listen", "rolled back").await.unwrap();
+    tx.rollback().await.unwrap();
+    let tx = db.transaction().await.unwrap();
+    tx.notify("test_db_listen", "committed").await.unwrap();
+    tx.commit().await.unwrap();
+    assert_eq!("committed", notifications.next().await.unwrap().payload());
+
+    // the listening connection is re-established when lost
+    let terminated = db
+        .execute(
+            "SELECT pg_terminate_backend(pid) FROM pg_stat_activity WHERE query = 'LISTEN \"test_db_listen\"'",
+            &[],
+        )
+        .await
+        .unwrap();
+    assert_eq!(1, terminated);
+
+    let mut received = None;
+    for _ in 0..50 {
+        db.notify("test_db_listen", "reconnected").await.unwrap();
+        let next = tokio::time::timeout(Duration::from_millis(100), notifications.next());
+        if let Ok(notification) = next.await {
+            received = notification;
+            break;
+        }
+    }
+    assert_eq!("reconnected", received.unwrap().payload());
+}
@@ -0,0 +1,91 @@
+use futures::FutureExt;
+use std::ops::Deref;
+
+use super::db::DB;
+use super::error::*;
+
+/// A transaction (or savepoint of an outer transaction) pinned to a single connection.
+///
+/// Dereferences to a [DB] running every query inside the transaction. It is rolled back when
+/// dropped without calling `commit`.
+///
+/// ```
+/// use oxidizer::*;
+///
+/// async fn transfer(db: &DB) -> DBResult<()> {
+///     let tx = db.transaction().await?;
+///     tx.execute("UPDATE accounts SET balance = balance - 10 WHERE id = $1", &[&1]).await?;
+///     tx.execute("UPDATE accounts SET balance = balance + 10 WHERE id = $1", &[&2]).await?;
+///     tx.commit().await
+/// }
+/// ```
+pub struct Transaction {
+    db: DB,
+    savepoint: Option<String>,
+    done: bool,
+}
+
+impl Transaction {
+    pub(super) fn new(db: DB, savepoint: Option<String>) -> Self {
+        Transaction {
+            db,
+            savepoint,
+            done: false,
+        }
+    }
+
+    fn build_rollback_query(&self) -> String {
+        match &self.savepoint {
+            Some(name) => format!("ROLLBACK TO SAVEPOINT {}", name),
+            None => "ROLLBACK".to_string(),
+        }
+    }
+
+    pub async fn commit(mut self) -> DBResult<()> {
+        self.done = true;
+        let query = match &self.savepoint {
+            Some(name) => format!("RELEASE SAVEPOINT {}", name),
+            None => "COMMIT".to_string(),
+        };
+
+        let client = self.db.client().await?;
+        client
+            .batch_execute(&query)
+            .await
+            .map_err(Error::PostgresError)
+    }
+
+    pub async fn rollback(mut self) -> DBResult<()> {
+        self.done = true;
+        let query = self.build_rollback_query();
+
+        let client = self.db.client().await?;
+        client
+            .batch_execute(&query)
+            .await
+            .map_err(Error::PostgresError)
+    }
+}
+
+impl Deref for Transaction {
+    type Target = DB;
+
+    fn deref(&self) -> &DB {
+        &self.db
+    }
+}
+
+impl Drop for Transaction {
+    fn drop(&mut self) {
+        if self.done {
+            return;
+        }
+
+        // the request is queued on the connection by the first poll, which keeps it ordered
+        // before any later query of an outer transaction
+        if let Some(client) = &self.db.pinned {
+            let query = self.build_rollback_query();
+            let _ = client.batch_execute(&query).now_or_never();
+        }
+    }
+}
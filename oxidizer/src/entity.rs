@@ -1,30 +1,135 @@
+use tokio_postgres::binary_copy::BinaryCopyOutRow;
 use tokio_postgres::Row;
 
 use super::async_trait;
-use super::db::{DBResult, DB};
-use super::db_types::ToSql;
+use super::copy::CopyOutStream;
+use super::db::{DBResult, DBStream, DB};
+use super::db_types::{FromSqlOwned, ToSql};
 use super::migration::Migration;
+use super::query::Query;
+use super::row::FromRow;
 
 /// Trait implemented by all derived Entitities
 #[async_trait]
 pub trait IEntity: Sized {
+    type PrimaryKey: ToSql + Sync + Clone;
+
     async fn save(&mut self, db: &DB) -> DBResult<bool>;
+    async fn insert(&mut self, db: &DB) -> DBResult<()>;
+    async fn update(&mut self, db: &DB) -> DBResult<()>;
+    async fn insert_many(db: &DB, entities: &mut [Self]) -> DBResult<()>;
+    async fn upsert_many(db: &DB, entities: &mut [Self]) -> DBResult<()>;
     async fn delete(&mut self, db: &DB) -> DBResult<bool>;
+    async fn update_where(
+        db: &DB,
+        condition: &str,
+        assignments: &str,
+        params: &'_ [&'_ (dyn ToSql + Sync)],
+    ) -> DBResult<u64>;
+    async fn update_where_returning(
+        db: &DB,
+        condition: &str,
+        assignments: &str,
+        params: &'_ [&'_ (dyn ToSql + Sync)],
+    ) -> DBResult<Vec<Self>>;
+    async fn delete_where(
+        db: &DB,
+        condition: &str,
+        params: &'_ [&'_ (dyn ToSql + Sync)],
+    ) -> DBResult<u64>;
+    async fn delete_where_returning(
+        db: &DB,
+        condition: &str,
+        params: &'_ [&'_ (dyn ToSql + Sync)],
+    ) -> DBResult<Vec<Self>>;
+    async fn copy_in<I>(db: &DB, entities: I) -> DBResult<u64>
+    where
+        I: IntoIterator<Item = Self> + Send,
+        I::IntoIter: Send;
+    async fn copy_out(db: &DB) -> DBResult<CopyOutStream<Self>>;
 
     fn is_synced_with_db(&self) -> bool;
+    #[allow(clippy::result_large_err)]
+    fn validate(&self) -> DBResult<()>;
+    fn get_dirty_fields(&self) -> Vec<String>;
 
     fn from_row(row: &Row) -> DBResult<Self>;
+    async fn load_rows(db: &DB, rows: &[Row]) -> DBResult<Vec<Self>>;
+    #[allow(clippy::result_large_err)]
+    fn from_copy_row(row: &BinaryCopyOutRow) -> DBResult<Self>;
     fn create_migration() -> DBResult<Migration>;
     fn get_table_name() -> String;
+    fn get_primary_key_name() -> String;
+    fn get_soft_delete_name() -> Option<String>;
+    fn get_primary_key(&self) -> &Self::PrimaryKey;
+    fn query<'a>() -> Query<'a, Self>;
 
     async fn find(
         db: &DB,
         query: &str,
         params: &'_ [&'_ (dyn ToSql + Sync)],
     ) -> DBResult<Vec<Self>>;
+    async fn find_stream(
+        db: &DB,
+        query: &str,
+        params: &'_ [&'_ (dyn ToSql + Sync)],
+    ) -> DBResult<DBStream<Self>>
+    where
+        Self: Send + 'static;
     async fn first(
         db: &DB,
         query: &str,
         params: &'_ [&'_ (dyn ToSql + Sync)],
     ) -> DBResult<Option<Self>>;
+
+    async fn find_by_pk(db: &DB, key: &Self::PrimaryKey) -> DBResult<Option<Self>>;
+    async fn get_by_pk(db: &DB, key: &Self::PrimaryKey) -> DBResult<Self>;
+    async fn reload(&mut self, db: &DB) -> DBResult<()>;
+
+    async fn count(db: &DB, condition: &str, params: &'_ [&'_ (dyn ToSql + Sync)])
+        -> DBResult<i64>;
+    async fn exists(
+        db: &DB,
+        condition: &str,
+        params: &'_ [&'_ (dyn ToSql + Sync)],
+    ) -> DBResult<bool>;
+    async fn sum<T>(
+        db: &DB,
+        column: &str,
+        condition: &str,
+        params: &'_ [&'_ (dyn ToSql + Sync)],
+    ) -> DBResult<Option<T>>
+    where
+        T: FromSqlOwned + Send;
+    async fn avg(
+        db: &DB,
+        column: &str,
+        condition: &str,
+        params: &'_ [&'_ (dyn ToSql + Sync)],
+    ) -> DBResult<Option<f64>>;
+    async fn min<T>(
+        db: &DB,
+        column: &str,
+        condition: &str,
+        params: &'_ [&'_ (dyn ToSql + Sync)],
+    ) -> DBResult<Option<T>>
+    where
+        T: FromSqlOwned + Send;
+    async fn max<T>(
+        db: &DB,
+        column: &str,
+        condition: &str,
+        params: &'_ [&'_ (dyn ToSql + Sync)],
+    ) -> DBResult<Option<T>>
+    where
+        T: FromSqlOwned + Send;
+    async fn group_by<T>(
+        db: &DB,
+        columns: &str,
+        aggregates: &str,
+        condition: &str,
+        params: &'_ [&'_ (dyn ToSql + Sync)],
+    ) -> DBResult<Vec<T>>
+    where
+        T: FromRow + Send;
 }
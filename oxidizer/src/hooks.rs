@@ -0,0 +1,67 @@
+//!
+//! # Lifecycle hooks
+//!
+//! Entities opting into `#[entity(hooks)]` must implement [EntityHooks]. The generated methods
+//! then call the hooks around persistence:
+//! - `before_save`/`after_save` around `save`, `insert` and `update`
+//! - `before_delete`/`after_delete` around `delete` (and `force_delete` of soft deletable entities)
+//! - `after_load` for every entity loaded from the database: `find`, `first`, `find_stream`, the
+//!   relation accessors, trashed scopes, changes, audit history and temporal `as_of` queries
+//!
+//! `copy_out` is the only loader skipping `after_load`: like `copy_in`, it is a bulk path decoding
+//! the binary copy rows without a connection free to run the hooks on.
+//!
+//! A hook returning `Err` aborts the operation and the error is returned to the caller. `after_*`
+//! hooks only run when the operation succeeded.
+//!
+//! With dirty tracking (see [Snapshot](crate::Snapshot)), `before_save` runs before the changes
+//! are computed so its updates are saved, and `after_save` is skipped when `save` found nothing
+//! to write.
+//!
+//! ```
+//! use oxidizer::*;
+//!
+//! #[derive(Entity, Default)]
+//! #[entity(hooks)]
+//! pub struct Account {
+//!     #[primary_key(increments)]
+//!     id: i32,
+//!     email: String,
+//! }
+//!
+//! #[async_trait]
+//! impl EntityHooks for Account {
+//!     async fn before_save(&mut self, _db: &DB) -> DBResult<()> {
+//!         self.email = self.email.trim().to_lowercase();
+//!         Ok(())
+//!     }
+//! }
+//! ```
+//!
+
+use super::async_trait;
+use super::db::{DBResult, DB};
+
+/// Extension points called by the generated persistence methods. Every hook defaults to a no-op.
+#[async_trait]
+pub trait EntityHooks: Send {
+    async fn before_save(&mut self, _db: &DB) -> DBResult<()> {
+        Ok(())
+    }
+
+    async fn after_save(&mut self, _db: &DB) -> DBResult<()> {
+        Ok(())
+    }
+
+    async fn before_delete(&mut self, _db: &DB) -> DBResult<()> {
+        Ok(())
+    }
+
+    async fn after_delete(&mut self, _db: &DB) -> DBResult<()> {
+        Ok(())
+    }
+
+    async fn after_load(&mut self, _db: &DB) -> DBResult<()> {
+        Ok(())
+    }
+}
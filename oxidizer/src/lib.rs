@@ -4,14 +4,27 @@
 //! #[async_trait]
 //! pub trait Entity: Sized {
 //!     async fn save(&mut self, db: &DB) -> DBResult<bool>;
+//!     async fn insert(&mut self, db: &DB) -> DBResult<()>;
+//!     async fn update(&mut self, db: &DB) -> DBResult<()>;
+//!     async fn insert_many(db: &DB, entities: &mut [Self]) -> DBResult<()>;
+//!     async fn upsert_many(db: &DB, entities: &mut [Self]) -> DBResult<()>;
 //!     async fn delete(&mut self, db: &DB) -> DBResult<bool>;
+//!     async fn update_where(db: &DB, condition: &str, assignments: &str, params: &'_ [&'_ (dyn ToSql + Sync)]) -> DBResult<u64>;
+//!     async fn delete_where(db: &DB, condition: &str, params: &'_ [&'_ (dyn ToSql + Sync)]) -> DBResult<u64>;
+//!     async fn copy_in<I: IntoIterator<Item = Self>>(db: &DB, entities: I) -> DBResult<u64>;
+//!     async fn copy_out(db: &DB) -> DBResult<CopyOutStream<Self>>;
 //!
 //!     fn from_row(row: &Row) -> Self;
 //!     fn create_migration() -> DBResult<Migration>;
 //!     fn get_table_name() -> String;
+//!     fn query<'a>() -> Query<'a, Self>;
 //!
 //!     async fn find(db: &DB, query: &str, params: &'_ [&'_ (dyn ToSql + Sync)]) -> DBResult<Vec<Self>>;
+//!     async fn find_stream(db: &DB, query: &str, params: &'_ [&'_ (dyn ToSql + Sync)]) -> DBResult<DBStream<Self>>;
 //!     async fn first(db: &DB, query: &str, params: &'_ [&'_ (dyn ToSql + Sync)]) -> DBResult<Option<Self>>;
+//!     async fn find_by_pk(db: &DB, key: &Self::PrimaryKey) -> DBResult<Option<Self>>;
+//!     async fn get_by_pk(db: &DB, key: &Self::PrimaryKey) -> DBResult<Self>;
+//!     async fn reload(&mut self, db: &DB) -> DBResult<()>;
 //! }
 //! ```
 //! ```
@@ -54,6 +67,37 @@
 //!
 //! ```
 //!
+//! `save` is an upsert: an entity with a non default primary key is inserted or overwritten.
+//! Use `insert` to fail with `Error::AlreadyExists` when the primary key is taken and `update`
+//! to fail with `Error::DoesNotExist` when no row matches the primary key.
+//!
+//! `insert_many` and `upsert_many` write a slice of entities with multi-row INSERTs and write the
+//! generated primary keys back into each entity. Large slices are split into several statements
+//! run in a single transaction.
+//!
+//! `update_where` and `delete_where` change every row matching a condition with a single statement
+//! and return the number of affected rows. The `update_where_returning` and `delete_where_returning`
+//! variants return the affected rows instead. Parameters are shared by the condition and the
+//! assignments, e.g. `Order::update_where(&db, "created_at < $1", "archived = $2", &[&limit, &true])`.
+//! Hooks and validations are not run, while versions, `#[updated_at]` and soft deletes are honoured.
+//!
+//! `get_by_pk` returns `Error::DoesNotExist` when `find_by_pk` finds nothing. `reload` refreshes
+//! the columns of an entity from its row, leaving `#[field_ignore]` fields untouched.
+//!
+//! `query()` returns a [Query] builder combining filters, ordering, limits, offset pagination
+//! with `paginate`, keyset pagination with `keyset`/`after` and row locks with `for_update`,
+//! `for_share`, `skip_locked` and `nowait`, see the [query] module.
+//!
+//! `find_stream` returns a [DBStream] decoding rows as they arrive instead of buffering the whole
+//! result, see `DB::query_stream`.
+//!
+//! `DB::transaction` starts a [Transaction] which dereferences to a `DB`, so it can be passed to
+//! every entity method. It is committed with `commit` and rolled back by `rollback` or when dropped.
+//! Inside a transaction `find_stream` fetches rows in batches through a cursor.
+//!
+//! `copy_in` and `copy_out` stream entities through a binary `COPY` for bulk loads and exports,
+//! see the [copy] module.
+//!
 //!
 //! ## Attributes
 //!
@@ -84,7 +128,8 @@
 //! ```
 //!
 //! ### #[indexed]
-//! Make the specified field indexed in the db
+//! Make the specified field indexed in the db.
+//! Also generates `find_by_<field>(&db, &value) -> DBResult<Vec<Self>>`.
 //!
 //! ```
 //! use oxidizer::*;
@@ -119,8 +164,25 @@
 //! }
 //! ```
 //!
+//! #### hooks
+//! Calls the [EntityHooks] of the entity around persistence. See [hooks](hooks/index.html)
+//!
+//! #### emit_changes
+//! Adds a trigger notifying the inserted, updated and deleted rows to the migration and generates
+//! `changes(&db) -> DBResult<DBStream<Change<Self>>>`. See [changes](changes/index.html)
+//!
+//! #### audited
+//! Adds a `<table>_history` table filled by a trigger to the migration and generates
+//! `history(&self, &db) -> DBResult<Vec<HistoryEntry<Self>>>`. See [audit](audit/index.html)
+//!
+//! #### temporal
+//! Adds a `<table>_versions` table keeping the `valid_from`/`valid_to` range of every version of
+//! the rows to the migration and generates `as_of(&db, timestamp) -> DBResult<Vec<Self>>` and
+//! `find_by_pk_as_of(&db, &key, timestamp)`. See [temporal](temporal/index.html)
+//!
 //! ### #[index]
-//! Creates a custom index/constraint on one or more column
+//! Creates a custom index/constraint on one or more column.
+//! A unique index of a single column generates `find_by_<column>(&db, &value) -> DBResult<Option<Self>>`.
 //!
 //! ```
 //! use oxidizer::*;
@@ -151,6 +213,54 @@
 //! }
 //! ```
 //!
+//! ### #[snapshot]
+//! Enables dirty tracking for the entity. See [snapshot](snapshot/index.html)
+//!
+//! ### #[version]
+//! Enables optimistic locking on an integer field. `save` and `update` only modify the row if
+//! its version still matches the loaded one, increment it and return `Error::StaleEntity` otherwise.
+//! The column defaults to `0` in the migration.
+//!
+//! ```
+//! use oxidizer::*;
+//! #[derive(Default, Entity)]
+//! struct Entity {
+//!     #[primary_key(increments)]
+//!     id: i32,
+//!     name: String,
+//!
+//!     #[version]
+//!     version: i32,
+//! }
+//! ```
+//!
+//! ### #[created_at] / #[updated_at]
+//! Fills `Option<DateTime<Utc>>` fields with `now()` when the entity is written. `created_at` is only
+//! set on insert, `updated_at` on every write. The columns default to `now()` in the migration and
+//! the values assigned by the database are written back into the entity.
+//!
+//! ```
+//! use oxidizer::*;
+//! use chrono::{DateTime, Utc};
+//! #[derive(Default, Entity)]
+//! struct Entity {
+//!     #[primary_key(increments)]
+//!     id: i32,
+//!
+//!     #[created_at]
+//!     created_at: Option<DateTime<Utc>>,
+//!     #[updated_at]
+//!     updated_at: Option<DateTime<Utc>>,
+//! }
+//! ```
+//!
+//! ### #[soft_delete]
+//! Makes `delete` set an `Option<DateTime<Utc>>` field instead of removing the row.
+//! See [soft_delete](soft_delete/index.html)
+//!
+//! ### #[validate]
+//! Validates the field before the entity is written. See [validation](validation/index.html)
+//!
 //! ### #[custom_type]
 //! The custom type attribute lets you override the default type provided by oxidizer.
 //!
@@ -207,6 +317,63 @@
 //! actual type and the overriden type. The error type from the `TryFrom` trait must implement the `std::fmt::Display` trait
 //!
 //!
+//! ## Partial entities
+//! `#[derive(Partial)]` generates a struct holding a subset of an entity's columns.
+//! Its `find`/`first` only select the declared columns instead of `SELECT *`.
+//! `#[custom_type]` and `#[field_ignore]` work the same way as in entities.
+//! ```
+//! use oxidizer::*;
+//!
+//! #[derive(Entity)]
+//! pub struct Person {
+//!     #[primary_key(increments)]
+//!     id: i32,
+//!     name: String,
+//!     bio: String,
+//! }
+//!
+//! #[derive(Partial)]
+//! #[partial_of(Person)]
+//! pub struct PersonName {
+//!     id: i32,
+//!     name: String,
+//! }
+//! ```
+//! This implements the [IPartial](partial::IPartial) trait for `PersonName`:
+//! ```ignore
+//! let names = PersonName::find(&db, "true", &[]).await?;
+//! ```
+//!
+//! ## Aggregates
+//! Every entity gets `count`, `exists`, `sum`, `avg`, `min` and `max` helpers that take the same
+//! condition and params as `find`. `group_by` selects the grouping columns followed by the aggregates
+//! and decodes each row into a tuple (or any [FromRow](row::FromRow) type).
+//! ```ignore
+//! let open = Order::count(&db, "status = $1", &[&"open"]).await?;
+//! let total: Option<i64> = Order::sum(&db, "amount", "true", &[]).await?;
+//! let average: Option<f64> = Order::avg(&db, "amount", "true", &[]).await?;
+//! let per_status: Vec<(String, i64)> = Order::group_by(&db, "status", "COUNT(*)", "true", &[]).await?;
+//! ```
+//!
+//! ## Decoding arbitrary queries
+//! `#[derive(FromRow)]` implements [FromRow](row::FromRow) for any struct with named fields so the results of
+//! hand written queries can be decoded with `DB::query_as` and `DB::query_one_as`.
+//! `#[custom_type]` and `#[field_ignore]` are supported.
+//! ```
+//! use oxidizer::*;
+//!
+//! #[derive(FromRow)]
+//! pub struct StatusCount {
+//!     status: String,
+//!     total: i64,
+//! }
+//! ```
+//! ```ignore
+//! let counts: Vec<StatusCount> = db
+//!     .query_as("SELECT status, COUNT(*) AS total FROM orders GROUP BY status", &[])
+//!     .await?;
+//! ```
+//!
 //! ## Relations
 //!
 //! ### #[relation]
@@ -239,6 +406,35 @@
 //! }
 //! ```
 //!
+//! ### Self-referential relations
+//! Setting `model="Self"` makes the relation point back to the same entity, which is useful for
+//! trees such as categories or org charts. The accessors are named after the field (without the `_id` suffix)
+//! and recursive `ancestors`/`descendants` helpers are generated as well:
+//! ```
+//! use oxidizer::*;
+//! #[derive(Entity)]
+//! struct Category {
+//!     #[primary_key(increments)]
+//!     id: i32,
+//!
+//!     #[relation(model="Self", key="id")]
+//!     parent_id: Option<i32>,
+//! }
+//! ```
+//!
+//! ```ignore
+//! #[oxidizer::async_trait]
+//! pub trait __AccessorCategoryToCategory {
+//!     async fn get_parent(&self, db: &oxidizer::db::DB) -> oxidizer::db::DBResult<Category>;
+//!     async fn set_parent(&mut self, db: &oxidizer::db::DB, v: &Category) -> oxidizer::db::DBResult<()>;
+//!     async fn ancestors(&self, db: &oxidizer::db::DB) -> oxidizer::db::DBResult<Vec<Category>>;
+//!     async fn descendants(&self, db: &oxidizer::db::DB, max_depth: Option<i32>) -> oxidizer::db::DBResult<Vec<Category>>;
+//! }
+//! ```
+//! `ancestors` returns the parent first and the root last. `descendants` is ordered by depth and
+//! `max_depth` limits how many levels are traversed (`None` means unlimited, `Some(0)` returns
+//! no rows).
+//!
 //! #[has_many]
 //! 1-to-many or many-to-many relations can be achieved using the `has_many` attribute
 //!
@@ -314,7 +510,78 @@
 //! }
 //! ```
 //!
+//! ### #[polymorphic]
+//! Polymorphic relations allow an entity to point to one of several models through a
+//! `(<name>_type, <name>_id)` column pair. Both fields must be declared in the struct and
+//! a compound index is created for them.
+//! ```
+//! use oxidizer::*;
 //!
+//! #[derive(Default, Entity)]
+//! pub struct Post {
+//!     #[primary_key(increments)]
+//!     id: i32,
+//! }
+//!
+//! #[derive(Default, Entity)]
+//! pub struct Photo {
+//!     #[primary_key(increments)]
+//!     id: i32,
+//! }
+//!
+//! #[derive(Default, Entity)]
+//! #[polymorphic(name="commentable", models="Post, Photo")]
+//! pub struct Comment {
+//!     #[primary_key(increments)]
+//!     id: i32,
+//!
+//!     commentable_type: String,
+//!     commentable_id: i32,
+//! }
+//! ```
+//! The type column stores the table name of the target. An enum of the possible targets (named
+//! `CommentCommentable` here, overridable with `enum_name`) and the following trait are generated:
+//! ```ignore
+//! pub enum CommentCommentable {
+//!     Post(Post),
+//!     Photo(Photo),
+//! }
+//!
+//! #[oxidizer::async_trait]
+//! pub trait __AccessorPolymorphicCommentCommentable {
+//!     async fn get_commentable(&self, db: &oxidizer::db::DB) -> oxidizer::db::DBResult<CommentCommentable>;
+//!     async fn set_commentable<T>(&mut self, db: &oxidizer::db::DB, v: &T) -> oxidizer::db::DBResult<()>
+//!     where
+//!         T: oxidizer::entity::IEntity + Sync,
+//!         T::PrimaryKey: Into<i32>;
+//! }
+//! ```
+//!
+//! ## Notifications
+//! `DB::listen` subscribes to a `LISTEN` channel on a dedicated connection that reconnects
+//! automatically, and `DB::notify` sends a payload to the listeners of a channel, for instance
+//! to invalidate caches across instances.
+//!
+//! ## Job queue
+//! The [queue] module provides a durable job queue on top of the same pool: `queue::enqueue`
+//! stores jobs in a migration managed table and `queue::Worker` runs them with retries, backoff
+//! and a dead letter state.
+//!
+//! ## Transactional outbox
+//! The [outbox] module commits events with the entity writes they describe: `outbox::publish`
+//! writes in the caller's transaction and `outbox::Relay` hands the committed messages, in the
+//! order of their commits, to a `Publisher` with at least once delivery.
+//!
+//!
+
+// the derives generate absolute `oxidizer::` paths, which must also resolve in this crate
+extern crate self as oxidizer;
+
+pub mod audit;
+
+pub mod changes;
+
+pub mod copy;
 
 pub mod db;
 pub use db::*;
@@ -322,8 +589,34 @@ pub use db::*;
 pub mod entity;
 pub use entity::*;
 
+pub mod hooks;
+pub use hooks::EntityHooks;
+
 pub mod migration;
 
+pub mod outbox;
+
+pub mod partial;
+pub use partial::*;
+
+pub mod query;
+pub use query::{Cursor, CursorPage, Page, Query};
+
+pub mod queue;
+
+pub mod row;
+pub use row::*;
+
+pub mod snapshot;
+pub use snapshot::Snapshot;
+
+pub mod soft_delete;
+
+pub mod temporal;
+
+pub mod validation;
+pub use validation::FieldError;
+
 /// Re-export of [async_trait::async_trait](https://crates.io/crates/async-trait)
 pub use async_trait::async_trait;
 pub use tokio_postgres;
@@ -66,6 +66,9 @@ pub struct Migration {
     pub name: String,
 
     pub raw: RawMigration,
+
+    /// Statements run after the barrel migration, such as functions and triggers
+    pub statements: Vec<String>,
 }
 
 impl Migration {
@@ -75,12 +78,19 @@ impl Migration {
             name: name.to_string(),
 
             raw: RawMigration::new(),
+
+            statements: Vec::new(),
         }
     }
 
     /// Builds the raw query from the migration
     pub fn make(&self) -> String {
-        self.raw.make::<Pg>()
+        let mut sql = self.raw.make::<Pg>();
+        for statement in self.statements.iter() {
+            sql.push_str(statement);
+            sql.push(';');
+        }
+        sql
     }
 }
 
@@ -0,0 +1,248 @@
+//!
+//! # Transactional outbox
+//!
+//! Events written with `publish` are stored in the `oxidizer_outbox` table, whose migration is
+//! returned by `OutboxMessage::create_migration()`. Writing them in the transaction of the
+//! entities they describe commits both atomically. A [Relay] then hands the unsent messages to a
+//! [Publisher] forwarding them to the message broker.
+//!
+//! Delivery is at least once: a message is only removed from the outbox after it is published,
+//! so it is published again when the relay stops in between. A single relay publishes at a time,
+//! the others skip their turn while it holds a transaction-level advisory lock.
+//!
+//! Messages are published in the order they were written by a transaction, and transactions
+//! started after another one committed are published after it. Messages of concurrent
+//! transactions are not ordered: a transaction committing first is published first even if it
+//! wrote its messages last. Use a single transaction, or a sequence number in the payload, for
+//! messages whose order matters.
+//!
+//! ```
+//! use oxidizer::*;
+//! use oxidizer::outbox::{self, OutboxMessage, Publisher, Relay};
+//!
+//! struct Broker;
+//!
+//! #[async_trait]
+//! impl Publisher for Broker {
+//!     async fn publish(&self, message: &OutboxMessage) -> Result<(), String> {
+//!         println!("{}: {}", message.topic, message.payload);
+//!         Ok(())
+//!     }
+//! }
+//!
+//! async fn place_order(db: &DB) -> DBResult<()> {
+//!     let tx = db.transaction().await?;
+//!     // ... write the order with &tx
+//!     outbox::publish(&tx, "orders", "{\"id\": 1}").await?;
+//!     tx.commit().await?;
+//!
+//!     Ok(())
+//! }
+//!
+//! async fn relay(db: &DB) -> DBResult<()> {
+//!     db.migrate_tables(&[OutboxMessage::create_migration()?]).await?;
+//!
+//!     Relay::new(db, Broker).run().await;
+//!     Ok(())
+//! }
+//! ```
+//!
+
+use chrono::{DateTime, Utc};
+use std::future::Future;
+#[cfg(any(test, feature = "test-utils"))]
+use std::sync::atomic::{AtomicBool, Ordering};
+#[cfg(any(test, feature = "test-utils"))]
+use std::sync::Mutex;
+use std::time::Duration;
+
+use super::async_trait;
+use super::db::listen::run_batches;
+use super::db::{DBResult, DB};
+use super::entity::IEntity;
+use super::Entity;
+
+/// Channel notified when messages are committed to the outbox
+pub const CHANNEL: &str = "oxidizer_outbox";
+
+/// Key of the advisory lock held by the relay publishing
+pub const RELAY_LOCK: i64 = 0x6f78_6f75_7462_6f78;
+
+/// A row of the outbox table
+#[derive(Entity, Debug, Clone)]
+#[entity(table_name = "oxidizer_outbox")]
+pub struct OutboxMessage {
+    /// Increasing with the order of the inserts, which is not the order of the commits
+    #[primary_key(increments)]
+    pub id: i32,
+    pub topic: String,
+    pub payload: String,
+
+    /// Number of failed publications
+    pub attempts: i32,
+    /// Error returned by the last failed publication
+    pub last_error: Option<String>,
+
+    #[created_at]
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+/// Writes a message to the outbox. Called with a transaction, the message is only published if
+/// the transaction commits.
+pub async fn publish(db: &DB, topic: &str, payload: &str) -> DBResult<OutboxMessage> {
+    let mut message = OutboxMessage {
+        id: 0,
+        topic: topic.to_string(),
+        payload: payload.to_string(),
+        attempts: 0,
+        last_error: None,
+        created_at: None,
+    };
+    message.insert(db).await?;
+
+    db.notify(CHANNEL, topic).await?;
+
+    Ok(message)
+}
+
+/// Sends the messages of the outbox to a message broker
+#[async_trait]
+pub trait Publisher: Send + Sync {
+    /// Publishes `message`, an `Err` stops the relay until its next attempt
+    async fn publish(&self, message: &OutboxMessage) -> Result<(), String>;
+}
+
+/// Publisher keeping the messages in memory, for tests. Available with the `test-utils` feature.
+#[cfg(any(test, feature = "test-utils"))]
+#[derive(Default)]
+pub struct TestPublisher {
+    published: Mutex<Vec<OutboxMessage>>,
+    failing: AtomicBool,
+}
+
+#[cfg(any(test, feature = "test-utils"))]
+impl TestPublisher {
+    pub fn new() -> Self {
+        TestPublisher::default()
+    }
+
+    /// Messages published so far, in order
+    pub fn published(&self) -> Vec<OutboxMessage> {
+        self.published.lock().unwrap().clone()
+    }
+
+    /// Makes the next publications fail, as if the broker was down
+    pub fn set_failing(&self, failing: bool) {
+        self.failing.store(failing, Ordering::SeqCst);
+    }
+}
+
+#[cfg(any(test, feature = "test-utils"))]
+#[async_trait]
+impl Publisher for TestPublisher {
+    async fn publish(&self, message: &OutboxMessage) -> Result<(), String> {
+        if self.failing.load(Ordering::SeqCst) {
+            return Err("broker unavailable".to_string());
+        }
+
+        self.published.lock().unwrap().push(message.clone());
+        Ok(())
+    }
+}
+
+/// Publishes the messages of the outbox
+pub struct Relay<P> {
+    db: DB,
+    publisher: P,
+    batch_size: i64,
+    poll_interval: Duration,
+}
+
+impl<P: Publisher> Relay<P> {
+    pub fn new(db: &DB, publisher: P) -> Self {
+        Relay {
+            db: db.clone(),
+            publisher,
+            batch_size: 100,
+            poll_interval: Duration::from_secs(5),
+        }
+    }
+
+    /// Maximum number of messages published by transaction
+    pub fn batch_size(mut self, batch_size: i64) -> Self {
+        self.batch_size = batch_size;
+        self
+    }
+
+    /// Time an idle relay waits for a notification before looking for messages again
+    pub fn poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    pub fn publisher(&self) -> &P {
+        &self.publisher
+    }
+
+    /// Publishes a batch of committed messages by id, returning the number of messages published.
+    /// The batch stops at the first message the publisher fails to publish.
+    pub async fn run_once(&self) -> DBResult<usize> {
+        let tx = self.db.transaction().await?;
+
+        let locked = tx
+            .query("SELECT pg_try_advisory_xact_lock($1)", &[&RELAY_LOCK])
+            .await?;
+        if !locked[0].get::<usize, bool>(0) {
+            tx.rollback().await?;
+            return Ok(0);
+        }
+
+        let messages = OutboxMessage::query()
+            .order_by("id")
+            .limit(self.batch_size)
+            .find(&tx)
+            .await?;
+
+        let mut published: Vec<i32> = Vec::new();
+        for message in messages.iter() {
+            if let Err(error) = self.publisher.publish(message).await {
+                OutboxMessage::update_where(
+                    &tx,
+                    "id = $1",
+                    "attempts = attempts + 1, last_error = $2",
+                    &[&message.id, &error],
+                )
+                .await?;
+                break;
+            }
+            published.push(message.id);
+        }
+
+        if !published.is_empty() {
+            OutboxMessage::delete_where(&tx, "id = ANY($1)", &[&published]).await?;
+        }
+        tx.commit().await?;
+
+        Ok(published.len())
+    }
+
+    /// Publishes messages forever, see `run_until`
+    pub async fn run(&self) {
+        self.run_until(futures::future::pending()).await
+    }
+
+    /// Publishes messages until `shutdown` completes, letting the current batch finish. Idle
+    /// relays wait for a notification of `publish` or `poll_interval`, whichever comes first.
+    /// Errors are logged and the batch retried after an increasing delay.
+    pub async fn run_until<S: Future<Output = ()>>(&self, shutdown: S) {
+        run_batches(
+            &self.db,
+            CHANNEL,
+            self.poll_interval,
+            "outbox relay",
+            || self.run_once(),
+            shutdown,
+        )
+        .await
+    }
+}
@@ -0,0 +1,24 @@
+use super::async_trait;
+use super::db::{DBResult, DB};
+use super::db_types::ToSql;
+use super::entity::IEntity;
+use super::row::FromRow;
+
+/// Trait implemented by all derived partial entities (a subset of an entity's columns)
+#[async_trait]
+pub trait IPartial: FromRow {
+    type Entity: IEntity;
+
+    fn get_columns() -> Vec<String>;
+
+    async fn find(
+        db: &DB,
+        query: &str,
+        params: &'_ [&'_ (dyn ToSql + Sync)],
+    ) -> DBResult<Vec<Self>>;
+    async fn first(
+        db: &DB,
+        query: &str,
+        params: &'_ [&'_ (dyn ToSql + Sync)],
+    ) -> DBResult<Option<Self>>;
+}
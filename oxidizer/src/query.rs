@@ -0,0 +1,565 @@
+//!
+//! # Query builder
+//!
+//! `MyEntity::query()` returns a [Query] composing conditions, ordering and limits without
+//! concatenating SQL strings. Every `filter` numbers its placeholders from `$1`, they are renumbered
+//! when the conditions are combined.
+//!
+//! ```
+//! use oxidizer::*;
+//!
+//! #[derive(Entity, Default)]
+//! pub struct Post {
+//!     #[primary_key(increments)]
+//!     id: i32,
+//!     author: String,
+//!     score: i32,
+//! }
+//!
+//! async fn list(db: &DB, author: &str, min_score: i32) -> DBResult<()> {
+//!     let posts = Post::query()
+//!         .filter("author = $1", &[&author])
+//!         .filter("score >= $1", &[&min_score])
+//!         .order_by("score DESC")
+//!         .limit(10)
+//!         .find(db)
+//!         .await?;
+//!
+//!     // offset pagination, pages start at 1
+//!     let page = Post::query().order_by("id").paginate(db, 2, 20).await?;
+//!     assert!(page.items.len() <= 20);
+//!
+//!     // keyset pagination, the cursor can be handed over to clients
+//!     let first = Post::query().keyset("score").limit(20).find_page(db).await?;
+//!     if let Some(cursor) = first.next {
+//!         let encoded = cursor.encode();
+//!         let second = Post::query()
+//!             .keyset("score")
+//!             .after(&Cursor::decode(&encoded)?)
+//!             .limit(20)
+//!             .find_page(db)
+//!             .await?;
+//!     }
+//!     Ok(())
+//! }
+//! ```
+//!
+//! Keyset pagination orders by the chosen column (which must not be nullable) and then by the
+//! primary key, so rows sharing the same value are neither skipped nor repeated. It replaces any
+//! `order_by` of the query. Keyset columns must be integers, text, dates, timestamps or uuids.
+//!
+//! ## Row locks
+//!
+//! `for_update` and `for_share` lock the selected rows until the end of the transaction, while
+//! `skip_locked` and `nowait` choose what happens with rows already locked by someone else. Run
+//! them on a [Transaction](crate::db::Transaction), otherwise the locks are released as soon as
+//! the query returns. Locks are only available through the query builder: `MyEntity::find`,
+//! `first` and the other generated methods never lock rows, use
+//! `MyEntity::query().filter(condition, params).for_update()` instead.
+//!
+//! ```
+//! use oxidizer::*;
+//!
+//! #[derive(Entity, Default)]
+//! pub struct Job {
+//!     #[primary_key(increments)]
+//!     id: i32,
+//!     state: String,
+//! }
+//!
+//! async fn claim(db: &DB) -> DBResult<Vec<Job>> {
+//!     let tx = db.transaction().await?;
+//!     let jobs = Job::query()
+//!         .filter("state = $1", &[&"pending"])
+//!         .for_update()
+//!         .skip_locked()
+//!         .limit(10)
+//!         .find(&tx)
+//!         .await?;
+//!     // ... mark the jobs as running
+//!     tx.commit().await?;
+//!     Ok(jobs)
+//! }
+//! ```
+//!
+
+use std::marker::PhantomData;
+
+use tokio_postgres::Row;
+
+use super::db::{DBResult, Error, DB};
+use super::db_types::ToSql;
+use super::entity::IEntity;
+
+const CURSOR_COLUMN_PREFIX: &str = "__oxidizer_cursor_";
+
+/// Types of the keyset columns a cursor can hold, they are cast to in the query
+const CURSOR_TYPES: &[&str] = &[
+    "int2",
+    "int4",
+    "int8",
+    "text",
+    "varchar",
+    "date",
+    "timestamp",
+    "timestamptz",
+    "uuid",
+];
+
+#[allow(clippy::result_large_err)]
+fn check_cursor_type(ty: &str) -> DBResult<()> {
+    match CURSOR_TYPES.contains(&ty) {
+        true => Ok(()),
+        false => Err(Error::Other(format!(
+            "Unsupported keyset column type {}",
+            ty
+        ))),
+    }
+}
+
+/// Position after the last row of a keyset page. It is opaque to clients through `encode`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cursor {
+    /// Type name and text representation of each key column
+    values: Vec<(String, String)>,
+}
+
+impl Cursor {
+    /// Encodes the cursor as an url safe string
+    pub fn encode(&self) -> String {
+        let raw = self
+            .values
+            .iter()
+            .map(|(ty, value)| format!("{}:{}", ty, value))
+            .collect::<Vec<String>>()
+            .join("\u{1f}");
+
+        raw.bytes().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    /// Decodes a cursor produced by `encode`
+    #[allow(clippy::result_large_err)]
+    pub fn decode(encoded: &str) -> DBResult<Cursor> {
+        let invalid = || Error::Other("Invalid cursor".to_string());
+
+        let bytes = encoded
+            .as_bytes()
+            .chunks(2)
+            .map(|pair| match pair.len() {
+                2 => std::str::from_utf8(pair)
+                    .ok()
+                    .and_then(|hex| u8::from_str_radix(hex, 16).ok()),
+                _ => None,
+            })
+            .collect::<Option<Vec<u8>>>()
+            .ok_or_else(invalid)?;
+        let raw = String::from_utf8(bytes).map_err(|_| invalid())?;
+
+        let mut values = vec![];
+        for part in raw.split('\u{1f}') {
+            let mut split = part.splitn(2, ':');
+            match (split.next(), split.next()) {
+                (Some(ty), Some(value)) if CURSOR_TYPES.contains(&ty) => {
+                    values.push((ty.to_string(), value.to_string()))
+                }
+                _ => return Err(invalid()),
+            }
+        }
+
+        Ok(Cursor { values })
+    }
+}
+
+/// A page of an offset pagination
+#[derive(Debug)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    /// Number of rows matching the query
+    pub total: i64,
+    /// Page number, starting at 1
+    pub page: i64,
+    pub per_page: i64,
+}
+
+impl<T> Page<T> {
+    pub fn total_pages(&self) -> i64 {
+        (self.total + self.per_page - 1) / self.per_page
+    }
+}
+
+/// A page of a keyset pagination
+#[derive(Debug)]
+pub struct CursorPage<T> {
+    pub items: Vec<T>,
+    /// Cursor of the next page, `None` on the last page
+    pub next: Option<Cursor>,
+}
+
+/// Renumbers the `$n` placeholders of `condition` by `offset`, skipping quoted literals
+fn renumber_placeholders(condition: &str, offset: usize) -> String {
+    let mut result = String::with_capacity(condition.len());
+    let mut chars = condition.chars().peekable();
+    let mut quote: Option<char> = None;
+
+    while let Some(c) = chars.next() {
+        result.push(c);
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None if c == '\'' || c == '"' => quote = Some(c),
+            None if c == '$' => {
+                let mut digits = String::new();
+                while let Some(d) = chars.peek().filter(|d| d.is_ascii_digit()) {
+                    digits.push(*d);
+                    chars.next();
+                }
+                match digits.parse::<usize>() {
+                    Ok(index) => result.push_str(&(index + offset).to_string()),
+                    Err(_) => result.push_str(&digits),
+                }
+            }
+            None => {}
+        }
+    }
+
+    result
+}
+
+/// Strength of the row locks taken by a [Query]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LockStrength {
+    /// `FOR UPDATE`
+    Update,
+    /// `FOR SHARE`
+    Share,
+}
+
+/// What a locking [Query] does with rows locked by another transaction
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LockWait {
+    /// Waits until the rows are released
+    Wait,
+    /// `SKIP LOCKED`
+    SkipLocked,
+    /// `NOWAIT`, failing right away
+    NoWait,
+}
+
+/// Composable `SELECT` of the entity `T`, created by `T::query()`
+pub struct Query<'a, T> {
+    scope: Option<String>,
+    conditions: Vec<String>,
+    params: Vec<&'a (dyn ToSql + Sync)>,
+    order_by: Vec<String>,
+    limit: Option<i64>,
+    offset: Option<i64>,
+    keyset: Option<(String, bool)>,
+    after: Option<Cursor>,
+    lock: Option<LockStrength>,
+    lock_wait: LockWait,
+    entity: PhantomData<T>,
+}
+
+impl<'a, T: IEntity> Query<'a, T> {
+    /// Creates a query always restricted by `scope`, e.g. the soft delete condition
+    pub fn new(scope: Option<&str>) -> Self {
+        Query {
+            scope: scope.map(|s| s.to_string()),
+            conditions: vec![],
+            params: vec![],
+            order_by: vec![],
+            limit: None,
+            offset: None,
+            keyset: None,
+            after: None,
+            lock: None,
+            lock_wait: LockWait::Wait,
+            entity: PhantomData,
+        }
+    }
+
+    /// Adds a condition combined with `AND`, its placeholders start at `$1`
+    pub fn filter(mut self, condition: &str, params: &[&'a (dyn ToSql + Sync)]) -> Self {
+        self.conditions
+            .push(renumber_placeholders(condition, self.params.len()));
+        self.params.extend_from_slice(params);
+        self
+    }
+
+    /// Adds an `ORDER BY` expression, e.g. `"name"` or `"created_at DESC"`
+    pub fn order_by(mut self, expression: &str) -> Self {
+        self.order_by.push(expression.to_string());
+        self
+    }
+
+    pub fn limit(mut self, limit: i64) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    pub fn offset(mut self, offset: i64) -> Self {
+        self.offset = Some(offset);
+        self
+    }
+
+    /// Paginates by `column` (then the primary key) in ascending order
+    pub fn keyset(mut self, column: &str) -> Self {
+        self.keyset = Some((column.to_string(), false));
+        self
+    }
+
+    /// Paginates by `column` (then the primary key) in descending order
+    pub fn keyset_desc(mut self, column: &str) -> Self {
+        self.keyset = Some((column.to_string(), true));
+        self
+    }
+
+    /// Starts the keyset pagination after `cursor`
+    pub fn after(mut self, cursor: &Cursor) -> Self {
+        self.after = Some(cursor.clone());
+        self
+    }
+
+    /// Locks the selected rows with `FOR UPDATE`
+    pub fn for_update(mut self) -> Self {
+        self.lock = Some(LockStrength::Update);
+        self
+    }
+
+    /// Locks the selected rows with `FOR SHARE`
+    pub fn for_share(mut self) -> Self {
+        self.lock = Some(LockStrength::Share);
+        self
+    }
+
+    /// Skips rows locked by other transactions, locking `FOR UPDATE` unless a lock was chosen
+    pub fn skip_locked(mut self) -> Self {
+        self.lock = self.lock.or(Some(LockStrength::Update));
+        self.lock_wait = LockWait::SkipLocked;
+        self
+    }
+
+    /// Fails instead of waiting for rows locked by other transactions, locking `FOR UPDATE`
+    /// unless a lock was chosen
+    pub fn nowait(mut self) -> Self {
+        self.lock = self.lock.or(Some(LockStrength::Update));
+        self.lock_wait = LockWait::NoWait;
+        self
+    }
+
+    fn build_lock(&self) -> String {
+        let strength = match self.lock {
+            Some(LockStrength::Update) => " FOR UPDATE",
+            Some(LockStrength::Share) => " FOR SHARE",
+            None => return String::new(),
+        };
+        let wait = match self.lock_wait {
+            LockWait::Wait => "",
+            LockWait::SkipLocked => " SKIP LOCKED",
+            LockWait::NoWait => " NOWAIT",
+        };
+
+        format!("{}{}", strength, wait)
+    }
+
+    /// Columns ordering a keyset pagination, which defaults to the primary key when `paging`
+    fn get_keyset_columns(&self, paging: bool) -> Option<(Vec<String>, bool)> {
+        let primary_key = T::get_primary_key_name();
+        match &self.keyset {
+            Some((column, desc)) if *column != primary_key => {
+                Some((vec![column.clone(), primary_key], *desc))
+            }
+            Some((_, desc)) => Some((vec![primary_key], *desc)),
+            None if paging || self.after.is_some() => Some((vec![primary_key], false)),
+            None => None,
+        }
+    }
+
+    fn build_where(&self, keyset: &Option<(Vec<String>, bool)>) -> String {
+        let mut conditions: Vec<String> = self.scope.iter().cloned().collect();
+        conditions.extend(self.conditions.iter().map(|c| format!("({})", c)));
+
+        if let (Some((columns, desc)), Some(cursor)) = (keyset, &self.after) {
+            let values: Vec<String> = cursor
+                .values
+                .iter()
+                .enumerate()
+                .map(|(i, (ty, _))| {
+                    format!(
+                        "CAST(CAST(${} AS text) AS {})",
+                        self.params.len() + i + 1,
+                        ty
+                    )
+                })
+                .collect();
+            conditions.push(format!(
+                "({}) {} ({})",
+                columns.join(", "),
+                if *desc { "<" } else { ">" },
+                values.join(", ")
+            ));
+        }
+
+        match conditions.is_empty() {
+            true => "TRUE".to_string(),
+            false => conditions.join(" AND "),
+        }
+    }
+
+    fn build_select(
+        &self,
+        keyset: &Option<(Vec<String>, bool)>,
+        limit: Option<i64>,
+        offset: Option<i64>,
+    ) -> String {
+        let mut query = "SELECT *".to_string();
+
+        if let Some((columns, _)) = keyset {
+            for (i, column) in columns.iter().enumerate() {
+                query += &format!(", ({})::text AS {}{}", column, CURSOR_COLUMN_PREFIX, i);
+            }
+        }
+
+        query += &format!(
+            " FROM \"{}\" WHERE {}",
+            T::get_table_name(),
+            self.build_where(keyset)
+        );
+
+        let order_by = match keyset {
+            Some((columns, desc)) => columns
+                .iter()
+                .map(|c| format!("{}{}", c, if *desc { " DESC" } else { "" }))
+                .collect(),
+            None => self.order_by.clone(),
+        };
+        if !order_by.is_empty() {
+            query += &format!(" ORDER BY {}", order_by.join(", "));
+        }
+        if let Some(limit) = limit {
+            query += &format!(" LIMIT {}", limit);
+        }
+        if let Some(offset) = offset {
+            query += &format!(" OFFSET {}", offset);
+        }
+        query += &self.build_lock();
+
+        query
+    }
+
+    fn get_cursor_values(&self) -> Vec<&String> {
+        match &self.after {
+            Some(cursor) => cursor.values.iter().map(|(_, value)| value).collect(),
+            None => vec![],
+        }
+    }
+
+    async fn run(
+        &self,
+        db: &DB,
+        keyset: &Option<(Vec<String>, bool)>,
+        limit: Option<i64>,
+        offset: Option<i64>,
+    ) -> DBResult<Vec<Row>> {
+        let query = self.build_select(keyset, limit, offset);
+
+        let cursor_values = self.get_cursor_values();
+        let mut params = self.params.clone();
+        for value in cursor_values.iter() {
+            params.push(*value);
+        }
+
+        db.query(&query, &params).await
+    }
+
+    pub async fn find(&self, db: &DB) -> DBResult<Vec<T>> {
+        let keyset = self.get_keyset_columns(false);
+        let rows = self.run(db, &keyset, self.limit, self.offset).await?;
+        T::load_rows(db, &rows).await
+    }
+
+    pub async fn first(&self, db: &DB) -> DBResult<Option<T>> {
+        let keyset = self.get_keyset_columns(false);
+        let rows = self.run(db, &keyset, Some(1), self.offset).await?;
+        Ok(T::load_rows(db, &rows).await?.pop())
+    }
+
+    /// Counts the rows matching the conditions, ignoring ordering, limit and offset
+    pub async fn count(&self, db: &DB) -> DBResult<i64> {
+        let query = format!(
+            "SELECT COUNT(*) FROM \"{}\" WHERE {}",
+            T::get_table_name(),
+            self.build_where(&self.get_keyset_columns(false))
+        );
+
+        let cursor_values = self.get_cursor_values();
+        let mut params = self.params.clone();
+        for value in cursor_values.iter() {
+            params.push(*value);
+        }
+
+        let rows = db.query(&query, &params).await?;
+        rows[0]
+            .try_get::<usize, i64>(0)
+            .map_err(Error::PostgresError)
+    }
+
+    /// Loads the page `page` (starting at 1) of `per_page` rows along with the total number of rows
+    pub async fn paginate(&self, db: &DB, page: i64, per_page: i64) -> DBResult<Page<T>> {
+        let page = std::cmp::max(page, 1);
+        let per_page = std::cmp::max(per_page, 1);
+
+        let offset = (page - 1)
+            .checked_mul(per_page)
+            .ok_or_else(|| Error::Other(format!("Page {} is out of range", page)))?;
+
+        let total = self.count(db).await?;
+        let keyset = self.get_keyset_columns(false);
+        let rows = self.run(db, &keyset, Some(per_page), Some(offset)).await?;
+        let items = T::load_rows(db, &rows).await?;
+
+        Ok(Page {
+            items,
+            total,
+            page,
+            per_page,
+        })
+    }
+
+    /// Loads the next keyset page of `limit` rows (all rows without a limit)
+    pub async fn find_page(&self, db: &DB) -> DBResult<CursorPage<T>> {
+        let keyset = self.get_keyset_columns(true);
+        // one more row tells whether there is a next page
+        let mut rows = self
+            .run(db, &keyset, self.limit.map(|l| l.saturating_add(1)), None)
+            .await?;
+
+        let mut next = None;
+        if let Some(limit) = self.limit {
+            if limit > 0 && rows.len() as i64 > limit {
+                rows.truncate(limit as usize);
+                let last = &rows[rows.len() - 1];
+
+                let mut values = vec![];
+                let columns = keyset.map(|(columns, _)| columns).unwrap_or_default();
+                for (i, column) in columns.iter().enumerate() {
+                    let ty = last
+                        .columns()
+                        .iter()
+                        .find(|c| c.name() == column)
+                        .map(|c| c.type_().name().to_string())
+                        .ok_or_else(|| Error::Other(format!("Unknown keyset column {}", column)))?;
+                    check_cursor_type(&ty)?;
+                    let value = last
+                        .try_get::<&str, String>(format!("{}{}", CURSOR_COLUMN_PREFIX, i).as_str())
+                        .map_err(Error::PostgresError)?;
+                    values.push((ty, value));
+                }
+                next = Some(Cursor { values });
+            }
+        }
+
+        let items = T::load_rows(db, &rows).await?;
+        Ok(CursorPage { items, next })
+    }
+}
@@ -0,0 +1,417 @@
+//!
+//! # Job queue
+//!
+//! A durable job queue stored in the `oxidizer_jobs` table, whose migration is returned by
+//! `Job::create_migration()` like any other entity.
+//!
+//! - `enqueue` stores a job in the default queue and wakes idle workers through `NOTIFY`
+//! - a [Worker] claims due jobs with `FOR UPDATE SKIP LOCKED`, so any number of workers can share
+//!   a queue, and passes them to a [JobHandler]
+//! - jobs completed successfully are deleted, failed jobs are retried with an exponential
+//!   [Backoff] and moved to the dead state after `max_attempts`
+//! - jobs claimed by a worker that stopped are claimed again after `lock_timeout`, the stale
+//!   worker can then no longer complete nor fail them
+//! - a worker keeps running through errors, such as a lost connection, until it is shut down
+//!
+//! ```
+//! use oxidizer::*;
+//! use oxidizer::queue::{self, Job, JobHandler, Worker};
+//!
+//! struct SendEmail;
+//!
+//! #[async_trait]
+//! impl JobHandler for SendEmail {
+//!     async fn handle(&self, _db: &DB, job: &Job) -> Result<(), String> {
+//!         println!("sending {}", job.payload);
+//!         Ok(())
+//!     }
+//! }
+//!
+//! async fn start(db: &DB) -> DBResult<()> {
+//!     db.migrate_tables(&[Job::create_migration()?]).await?;
+//!
+//!     queue::enqueue(db, "{\"to\": \"me@example.com\"}", chrono::Utc::now()).await?;
+//!
+//!     Worker::new(db, SendEmail).max_attempts(3).run().await;
+//!     Ok(())
+//! }
+//! ```
+//!
+
+use chrono::{DateTime, Utc};
+use std::convert::TryFrom;
+use std::fmt;
+use std::future::Future;
+use std::time::Duration;
+
+use super::async_trait;
+use super::db::listen::run_batches;
+use super::db::{DBResult, DB};
+use super::entity::IEntity;
+use super::Entity;
+
+/// Queue of the jobs enqueued by `enqueue`
+pub const DEFAULT_QUEUE: &str = "default";
+
+/// Channel notified with the queue name when a job is enqueued
+pub const CHANNEL: &str = "oxidizer_jobs";
+
+/// State of a [Job]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum JobState {
+    /// Waiting for `run_at`
+    Pending,
+    /// Claimed by a worker
+    Running,
+    /// Failed `max_attempts` times, the dead letter state
+    Dead,
+}
+
+impl JobState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            JobState::Pending => "pending",
+            JobState::Running => "running",
+            JobState::Dead => "dead",
+        }
+    }
+}
+
+/// Error decoding an unknown [JobState]
+#[derive(Debug)]
+pub struct InvalidJobState(String);
+
+impl fmt::Display for InvalidJobState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid job state {}", self.0)
+    }
+}
+
+impl TryFrom<&JobState> for String {
+    type Error = InvalidJobState;
+
+    fn try_from(state: &JobState) -> Result<Self, Self::Error> {
+        Ok(state.as_str().to_string())
+    }
+}
+
+impl TryFrom<String> for JobState {
+    type Error = InvalidJobState;
+
+    fn try_from(state: String) -> Result<Self, Self::Error> {
+        match state.as_str() {
+            "pending" => Ok(JobState::Pending),
+            "running" => Ok(JobState::Running),
+            "dead" => Ok(JobState::Dead),
+            _ => Err(InvalidJobState(state)),
+        }
+    }
+}
+
+/// A row of the jobs table
+#[derive(Entity, Debug)]
+#[entity(table_name = "oxidizer_jobs")]
+#[index(name = "oxidizer_jobs_claim", columns = "queue, state, run_at")]
+pub struct Job {
+    #[primary_key(increments)]
+    pub id: i32,
+    pub queue: String,
+    pub payload: String,
+
+    #[custom_type(ty = "String")]
+    pub state: JobState,
+    /// Number of times the job was claimed
+    pub attempts: i32,
+    /// The job is not claimed before this time
+    pub run_at: DateTime<Utc>,
+    pub locked_at: Option<DateTime<Utc>>,
+    /// Error returned by the last failed attempt
+    pub last_error: Option<String>,
+
+    #[created_at]
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+/// Enqueues `payload` in the default queue, to be run at `run_at`
+pub async fn enqueue(db: &DB, payload: &str, run_at: DateTime<Utc>) -> DBResult<Job> {
+    enqueue_to(db, DEFAULT_QUEUE, payload, run_at).await
+}
+
+/// Enqueues `payload` in `queue`, to be run at `run_at`. Inside a transaction the job is only
+/// visible, and the workers woken, once it is committed.
+pub async fn enqueue_to(
+    db: &DB,
+    queue: &str,
+    payload: &str,
+    run_at: DateTime<Utc>,
+) -> DBResult<Job> {
+    let mut job = Job {
+        id: 0,
+        queue: queue.to_string(),
+        payload: payload.to_string(),
+        state: JobState::Pending,
+        attempts: 0,
+        run_at,
+        locked_at: None,
+        last_error: None,
+        created_at: None,
+    };
+    job.insert(db).await?;
+
+    db.notify(CHANNEL, queue).await?;
+
+    Ok(job)
+}
+
+/// Jobs of `queue` that failed too many times
+pub async fn dead_jobs(db: &DB, queue: &str) -> DBResult<Vec<Job>> {
+    Job::query()
+        .filter(
+            "queue = $1 AND state = $2",
+            &[&queue, &JobState::Dead.as_str()],
+        )
+        .order_by("id")
+        .find(db)
+        .await
+}
+
+/// Moves a dead job back to the queue to run right away, with its attempts reset
+pub async fn retry(db: &DB, id: i32) -> DBResult<bool> {
+    let updated = Job::update_where(
+        db,
+        "id = $1 AND state = $2",
+        "state = $3, attempts = 0, run_at = now(), locked_at = NULL",
+        &[&id, &JobState::Dead.as_str(), &JobState::Pending.as_str()],
+    )
+    .await?;
+
+    Ok(updated == 1)
+}
+
+/// Exponential delay before retrying a failed job: `base * 2^(attempts - 1)`, at most `max`
+#[derive(Debug, Clone)]
+pub struct Backoff {
+    pub base: Duration,
+    pub max: Duration,
+}
+
+impl Backoff {
+    pub fn delay(&self, attempts: i32) -> Duration {
+        let exponent = (attempts - 1).clamp(0, 31) as u32;
+        let delay = self
+            .base
+            .checked_mul(2u32.pow(exponent))
+            .unwrap_or(self.max);
+        std::cmp::min(delay, self.max)
+    }
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Backoff {
+            base: Duration::from_secs(1),
+            max: Duration::from_secs(3600),
+        }
+    }
+}
+
+/// Runs the jobs claimed by a [Worker]
+#[async_trait]
+pub trait JobHandler: Send + Sync {
+    /// Runs `job`, an `Err` fails the attempt and is stored in `last_error`
+    async fn handle(&self, db: &DB, job: &Job) -> Result<(), String>;
+}
+
+/// Claims and runs the jobs of a queue
+pub struct Worker<H> {
+    db: DB,
+    queue: String,
+    handler: H,
+    batch_size: i64,
+    max_attempts: i32,
+    backoff: Backoff,
+    poll_interval: Duration,
+    lock_timeout: Duration,
+}
+
+impl<H: JobHandler> Worker<H> {
+    /// Creates a worker of the default queue
+    pub fn new(db: &DB, handler: H) -> Self {
+        Worker {
+            db: db.clone(),
+            queue: DEFAULT_QUEUE.to_string(),
+            handler,
+            batch_size: 10,
+            max_attempts: 5,
+            backoff: Backoff::default(),
+            poll_interval: Duration::from_secs(5),
+            lock_timeout: Duration::from_secs(300),
+        }
+    }
+
+    pub fn queue(mut self, queue: &str) -> Self {
+        self.queue = queue.to_string();
+        self
+    }
+
+    /// Maximum number of jobs claimed at once
+    pub fn batch_size(mut self, batch_size: i64) -> Self {
+        self.batch_size = batch_size;
+        self
+    }
+
+    /// Number of attempts before a job is dead
+    pub fn max_attempts(mut self, max_attempts: i32) -> Self {
+        self.max_attempts = max_attempts;
+        self
+    }
+
+    pub fn backoff(mut self, backoff: Backoff) -> Self {
+        self.backoff = backoff;
+        self
+    }
+
+    /// Time an idle worker waits for a notification before looking for due jobs again
+    pub fn poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    /// Time after which a job still running is considered abandoned and claimed again
+    pub fn lock_timeout(mut self, lock_timeout: Duration) -> Self {
+        self.lock_timeout = lock_timeout;
+        self
+    }
+
+    /// Claims up to `batch_size` due jobs, skipping the ones locked by other workers. Abandoned
+    /// jobs which already used their last attempt are moved to the dead jobs instead.
+    pub async fn claim(&self) -> DBResult<Vec<Job>> {
+        let pending = JobState::Pending.as_str();
+        let running = JobState::Running.as_str();
+        let lock_timeout = self.lock_timeout.as_secs_f64();
+
+        let tx = self.db.transaction().await?;
+        let jobs = Job::query()
+            .filter("queue = $1", &[&self.queue])
+            .filter(
+                "(state = $1 AND run_at <= now()) OR (state = $2 AND locked_at < now() - make_interval(secs => $3))",
+                &[&pending, &running, &lock_timeout],
+            )
+            .order_by("run_at")
+            .order_by("id")
+            .for_update()
+            .skip_locked()
+            .limit(self.batch_size)
+            .find(&tx)
+            .await?;
+
+        let (exhausted, jobs): (Vec<Job>, Vec<Job>) = jobs
+            .into_iter()
+            .partition(|job| job.state == JobState::Running && job.attempts >= self.max_attempts);
+
+        if !exhausted.is_empty() {
+            let ids: Vec<i32> = exhausted.iter().map(|job| job.id).collect();
+            Job::update_where(
+                &tx,
+                "id = ANY($1)",
+                "state = $2, locked_at = NULL, last_error = $3",
+                &[
+                    &ids,
+                    &JobState::Dead.as_str(),
+                    &"Lock timed out on the last attempt",
+                ],
+            )
+            .await?;
+        }
+
+        if jobs.is_empty() {
+            tx.commit().await?;
+            return Ok(jobs);
+        }
+
+        let ids: Vec<i32> = jobs.iter().map(|job| job.id).collect();
+        let mut claimed = Job::update_where_returning(
+            &tx,
+            "id = ANY($1)",
+            "state = $2, attempts = attempts + 1, locked_at = now()",
+            &[&ids, &running],
+        )
+        .await?;
+        tx.commit().await?;
+
+        claimed.sort_by_key(|job| (job.run_at, job.id));
+        Ok(claimed)
+    }
+
+    /// Fails an attempt of `job`, unless the job was claimed again since
+    async fn fail(&self, job: &Job, message: &str) -> DBResult<()> {
+        if job.attempts >= self.max_attempts {
+            Job::update_where(
+                &self.db,
+                "id = $1 AND attempts = $4",
+                "state = $2, locked_at = NULL, last_error = $3",
+                &[&job.id, &JobState::Dead.as_str(), &message, &job.attempts],
+            )
+            .await?;
+            return Ok(());
+        }
+
+        let delay = self.backoff.delay(job.attempts).as_secs_f64();
+        Job::update_where(
+            &self.db,
+            "id = $1 AND attempts = $5",
+            "state = $2, locked_at = NULL, last_error = $3, run_at = now() + make_interval(secs => $4)",
+            &[&job.id, &JobState::Pending.as_str(), &message, &delay, &job.attempts],
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    /// Claims a batch of jobs and runs them, returning the number of jobs claimed. An error
+    /// completing or failing a job does not stop the rest of the batch, the first one is returned
+    /// once every job ran and the job is claimed again after the lock timeout.
+    pub async fn run_once(&self) -> DBResult<usize> {
+        let jobs = self.claim().await?;
+
+        let mut result = Ok(jobs.len());
+        for job in jobs.iter() {
+            let completed = match self.handler.handle(&self.db, job).await {
+                Ok(()) => {
+                    let condition = "id = $1 AND attempts = $2";
+                    Job::delete_where(&self.db, condition, &[&job.id, &job.attempts])
+                        .await
+                        .map(|_| ())
+                }
+                Err(message) => self.fail(job, &message).await,
+            };
+
+            if let (Ok(_), Err(error)) = (&result, completed) {
+                result = Err(error);
+            }
+        }
+
+        result
+    }
+
+    /// Runs jobs forever, see `run_until`
+    pub async fn run(&self) {
+        self.run_until(futures::future::pending()).await
+    }
+
+    /// Runs jobs until `shutdown` completes, letting the current batch finish. Idle workers wait
+    /// for a notification of `enqueue` or `poll_interval`, whichever comes first. Errors are
+    /// logged and the batch retried after an increasing delay.
+    pub async fn run_until<S: Future<Output = ()>>(&self, shutdown: S) {
+        let name = format!("worker of queue {}", self.queue);
+        run_batches(
+            &self.db,
+            CHANNEL,
+            self.poll_interval,
+            &name,
+            || self.run_once(),
+            shutdown,
+        )
+        .await
+    }
+}
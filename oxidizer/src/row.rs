@@ -0,0 +1,30 @@
+use tokio_postgres::types::FromSqlOwned;
+use tokio_postgres::Row;
+
+use super::db::{DBResult, Error};
+
+/// Trait implemented by structs that can be decoded from arbitrary query results
+pub trait FromRow: Sized {
+    fn from_row(row: &Row) -> DBResult<Self>;
+}
+
+/// Tuples are decoded by column position, which makes them handy for aggregate queries
+macro_rules! impl_from_row_for_tuple {
+    ($($idx:tt $ty:ident),+) => {
+        impl<$($ty),+> FromRow for ($($ty,)+)
+        where
+            $($ty: FromSqlOwned,)+
+        {
+            fn from_row(row: &Row) -> DBResult<Self> {
+                Ok(($(row.try_get::<usize, $ty>($idx).map_err(Error::PostgresError)?,)+))
+            }
+        }
+    };
+}
+
+impl_from_row_for_tuple!(0 A);
+impl_from_row_for_tuple!(0 A, 1 B);
+impl_from_row_for_tuple!(0 A, 1 B, 2 C);
+impl_from_row_for_tuple!(0 A, 1 B, 2 C, 3 D);
+impl_from_row_for_tuple!(0 A, 1 B, 2 C, 3 D, 4 E);
+impl_from_row_for_tuple!(0 A, 1 B, 2 C, 3 D, 4 E, 5 F);
@@ -0,0 +1,86 @@
+//!
+//! # Dirty tracking
+//!
+//! Entities can opt into dirty tracking by declaring a `#[snapshot]` field of type [Snapshot].
+//! The snapshot holds the encoded column values as they were last loaded from or saved to the
+//! database. `save` then only sends an `UPDATE` with the columns that changed since, or no
+//! query at all when the entity is clean, in which case the `after_save` hook does not run either.
+//! Like `update`, it fails with `Error::DoesNotExist` when the row was deleted in the meantime.
+//!
+//! ```
+//! use oxidizer::*;
+//!
+//! #[derive(Entity, Default)]
+//! pub struct Account {
+//!     #[primary_key(increments)]
+//!     id: i32,
+//!     name: String,
+//!     email: String,
+//!
+//!     #[snapshot]
+//!     snapshot: Snapshot,
+//! }
+//! ```
+//!
+
+use bytes::BytesMut;
+use tokio_postgres::types::{IsNull, ToSql, Type};
+
+/// Column values of an entity as they were last loaded from or saved to the database
+#[derive(Debug, Clone, Default)]
+pub struct Snapshot {
+    values: Option<Vec<Option<Vec<u8>>>>,
+}
+
+impl Snapshot {
+    /// Creates a snapshot from values encoded with [encode]
+    pub fn capture(values: Vec<Option<Vec<u8>>>) -> Self {
+        Snapshot {
+            values: Some(values),
+        }
+    }
+
+    /// Whether the snapshot reflects a row in the database
+    pub fn is_captured(&self) -> bool {
+        self.values.is_some()
+    }
+
+    /// Forgets the captured values, making every column dirty
+    pub fn clear(&mut self) {
+        self.values = None;
+    }
+
+    /// Returns the indexes of the values that differ from the captured ones.
+    /// Values that could not be encoded are always reported as changed.
+    pub fn get_changed(&self, values: &[Option<Vec<u8>>]) -> Vec<usize> {
+        let captured = match self.values.as_ref() {
+            Some(captured) => captured,
+            None => return (0..values.len()).collect(),
+        };
+
+        values
+            .iter()
+            .enumerate()
+            .filter(|(i, value)| match (value, captured.get(*i)) {
+                (Some(value), Some(Some(captured))) => value != captured,
+                _ => true,
+            })
+            .map(|(i, _)| i)
+            .collect()
+    }
+}
+
+/// Encodes a value into its binary wire format so it can be compared against a snapshot
+pub fn encode<T: ToSql>(value: &T) -> Option<Vec<u8>> {
+    let mut buf = BytesMut::new();
+    match value.to_sql(&Type::UNKNOWN, &mut buf) {
+        Ok(IsNull::Yes) => Some(vec![0]),
+        Ok(IsNull::No) => {
+            let mut encoded = Vec::with_capacity(buf.len() + 1);
+            encoded.push(1);
+            encoded.extend_from_slice(&buf);
+            Some(encoded)
+        }
+        Err(_) => None,
+    }
+}
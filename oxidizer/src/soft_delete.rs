@@ -0,0 +1,118 @@
+//!
+//! # Soft delete
+//!
+//! Entities can opt into soft deletes by marking an `Option<DateTime<Utc>>` field with
+//! `#[soft_delete]`. `delete` then sets the field to `now()` instead of removing the row and
+//! the generated `find`, `first`, aggregates, relation accessors and partials skip trashed rows.
+//! A trashed row also ends the walk of `ancestors` and `descendants` in self relations.
+//!
+//! The derive also generates the following escape hatches:
+//! - `with_trashed()` and `only_trashed()` return a [Trashed] scope to query trashed rows
+//! - `restore(&db)` clears the field of a trashed entity
+//! - `force_delete(&db)` removes the row from the database
+//!
+//! ```
+//! use oxidizer::*;
+//! use chrono::{DateTime, Utc};
+//!
+//! #[derive(Entity, Default)]
+//! pub struct Customer {
+//!     #[primary_key(increments)]
+//!     id: i32,
+//!     name: String,
+//!
+//!     #[soft_delete]
+//!     deleted_at: Option<DateTime<Utc>>,
+//! }
+//! ```
+//!
+
+use std::marker::PhantomData;
+
+use super::db::{DBResult, DB};
+use super::db_types::ToSql;
+use super::entity::IEntity;
+
+/// Which rows a [Trashed] scope selects
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TrashedScope {
+    /// Both trashed and non trashed rows
+    WithTrashed,
+    /// Only trashed rows
+    OnlyTrashed,
+}
+
+/// Queries of a soft deletable entity that include trashed rows
+pub struct Trashed<T> {
+    column: &'static str,
+    scope: TrashedScope,
+    entity: PhantomData<T>,
+}
+
+impl<T: IEntity> Trashed<T> {
+    /// Creates a scope over the soft delete `column` of `T`
+    pub fn new(column: &'static str, scope: TrashedScope) -> Self {
+        Trashed {
+            column,
+            scope,
+            entity: PhantomData,
+        }
+    }
+
+    fn build_condition(&self, condition: &str) -> String {
+        match self.scope {
+            TrashedScope::WithTrashed => condition.to_string(),
+            TrashedScope::OnlyTrashed => format!("{} IS NOT NULL AND ({})", self.column, condition),
+        }
+    }
+
+    pub async fn find(
+        &self,
+        db: &DB,
+        condition: &str,
+        params: &'_ [&'_ (dyn ToSql + Sync)],
+    ) -> DBResult<Vec<T>> {
+        let query = format!(
+            "SELECT * FROM \"{}\" WHERE {}",
+            T::get_table_name(),
+            self.build_condition(condition)
+        );
+        let rows = db.query(&query, params).await?;
+
+        T::load_rows(db, &rows).await
+    }
+
+    pub async fn first(
+        &self,
+        db: &DB,
+        condition: &str,
+        params: &'_ [&'_ (dyn ToSql + Sync)],
+    ) -> DBResult<Option<T>> {
+        let query = format!(
+            "SELECT * FROM \"{}\" WHERE {} LIMIT 1",
+            T::get_table_name(),
+            self.build_condition(condition)
+        );
+        let rows = db.query(&query, params).await?;
+
+        Ok(T::load_rows(db, &rows).await?.pop())
+    }
+
+    pub async fn count(
+        &self,
+        db: &DB,
+        condition: &str,
+        params: &'_ [&'_ (dyn ToSql + Sync)],
+    ) -> DBResult<i64> {
+        let query = format!(
+            "SELECT COUNT(*) FROM \"{}\" WHERE {}",
+            T::get_table_name(),
+            self.build_condition(condition)
+        );
+        let rows = db.query(&query, params).await?;
+
+        rows[0]
+            .try_get::<usize, i64>(0)
+            .map_err(super::db::Error::PostgresError)
+    }
+}
@@ -0,0 +1,138 @@
+//!
+//! # Temporal tables
+//!
+//! Entities declared with `#[entity(temporal)]` get a `<table>_versions` table and a trigger in
+//! their `create_migration()`. Every version of a row is kept with the `valid_from`/`valid_to`
+//! range during which it was current, `valid_to` being `NULL` for the current version and the
+//! time of the delete for deleted rows. The ranges follow the time each row is written, as
+//! `clock_timestamp()`, rather than the start of its transaction: a transaction started before a
+//! concurrent change of the row still closes that version after it began.
+//!
+//! `MyEntity::as_of(&db, timestamp)` reconstructs the rows as they were at an instant and
+//! `MyEntity::find_by_pk_as_of(&db, &key, timestamp)` a single one. Soft deleted rows are
+//! returned like any other version.
+//!
+//! ```
+//! use chrono::{TimeZone, Utc};
+//! use oxidizer::*;
+//!
+//! #[derive(Entity)]
+//! #[entity(temporal)]
+//! pub struct Customer {
+//!     #[primary_key(increments)]
+//!     id: i32,
+//!     address: String,
+//! }
+//!
+//! async fn address_on_march_first(db: &DB, id: i32) -> DBResult<Option<String>> {
+//!     let at = Utc.ymd(2020, 3, 1).and_hms(0, 0, 0);
+//!     let customer = Customer::find_by_pk_as_of(db, &id, at).await?;
+//!
+//!     Ok(customer.map(|c| c.address))
+//! }
+//! ```
+//!
+
+use chrono::{DateTime, Utc};
+
+use super::db::{DBResult, DB};
+use super::entity::IEntity;
+use super::migration::Migration;
+
+/// Instant of an `as_of` query
+pub type Timestamp = DateTime<Utc>;
+
+/// Name of the versions table of `table`
+pub fn versions_table(table: &str) -> String {
+    format!("{}_versions", table)
+}
+
+/// Adds the versions table of `table`, keyed by its primary key of type `key_type`, and the
+/// versioning trigger to the migration of entities declared with `#[entity(temporal)]`
+pub fn add_versions(
+    m: &mut Migration,
+    table: &str,
+    primary_key: &str,
+    key_type: barrel::types::Type,
+) {
+    let versions = versions_table(table);
+    let key_index = format!("{}_row_key", versions);
+    let range_index = format!("{}_range", versions);
+
+    m.raw.create_table(versions.as_str(), move |t| {
+        t.add_column("id", barrel::types::custom("SERIAL").primary(true));
+        t.add_column("row_key", key_type.clone().nullable(false));
+        t.add_column("row", barrel::types::custom("jsonb"));
+        t.add_column(
+            "valid_from",
+            barrel::types::custom("timestamp with time zone"),
+        );
+        t.add_column(
+            "valid_to",
+            barrel::types::custom("timestamp with time zone").nullable(true),
+        );
+        t.add_index(
+            key_index.as_str(),
+            barrel::types::index(vec!["row_key", "valid_from"]),
+        );
+        t.add_index(
+            range_index.as_str(),
+            barrel::types::index(vec!["valid_from", "valid_to"]),
+        );
+    });
+
+    // the row lock taken by the change orders the writes, so the clock never closes a version
+    // before it started, unlike the transaction start time of now()
+    m.statements.push(format!(
+        "CREATE OR REPLACE FUNCTION \"{table}_temporal\"() RETURNS trigger AS $$
+DECLARE
+    changed_at TIMESTAMP WITH TIME ZONE := clock_timestamp();
+BEGIN
+    IF TG_OP = 'UPDATE' OR TG_OP = 'DELETE' THEN
+        UPDATE \"{versions}\" SET valid_to = changed_at WHERE row_key = OLD.\"{pk}\" AND valid_to IS NULL;
+    END IF;
+    IF TG_OP = 'INSERT' OR TG_OP = 'UPDATE' THEN
+        INSERT INTO \"{versions}\" (row_key, row, valid_from) VALUES (NEW.\"{pk}\", to_jsonb(NEW), changed_at);
+    END IF;
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql",
+        table = table,
+        versions = versions_table(table),
+        pk = primary_key,
+    ));
+    m.statements.push(format!(
+        "CREATE TRIGGER \"{table}_temporal\" AFTER INSERT OR UPDATE OR DELETE ON \"{table}\" FOR EACH ROW EXECUTE PROCEDURE \"{table}_temporal\"()",
+        table = table,
+    ));
+}
+
+fn build_as_of_query<T: IEntity>(condition: &str) -> String {
+    let table = T::get_table_name();
+    format!(
+        "SELECT r.* FROM \"{versions}\" v, jsonb_populate_record(NULL::\"{table}\", v.row) r WHERE v.valid_from <= $1 AND (v.valid_to IS NULL OR v.valid_to > $1) AND {condition} ORDER BY v.row_key",
+        versions = versions_table(&table),
+        table = table,
+        condition = condition,
+    )
+}
+
+/// Rows of `T` as they were at `at`, see `MyEntity::as_of`
+pub async fn as_of<T: IEntity>(db: &DB, at: Timestamp) -> DBResult<Vec<T>> {
+    let query = build_as_of_query::<T>("true");
+    let rows = db.query(&query, &[&at]).await?;
+
+    T::load_rows(db, &rows).await
+}
+
+/// Row of `T` with the primary key `key` as it was at `at`, see `MyEntity::find_by_pk_as_of`
+pub async fn find_by_pk_as_of<T: IEntity>(
+    db: &DB,
+    key: &T::PrimaryKey,
+    at: Timestamp,
+) -> DBResult<Option<T>> {
+    let query = build_as_of_query::<T>("v.row_key = $2");
+    let rows = db.query(&query, &[&at, key]).await?;
+
+    Ok(T::load_rows(db, &rows).await?.pop())
+}
@@ -1,9 +1,5 @@
 use super::*;
 
-mod oxidizer {
-    pub use crate::*;
-}
-
 use chrono::{DateTime, Utc};
 
 #[derive(Entity, Default)]
@@ -112,6 +108,241 @@ pub struct TestManyToMany {
     entity_id: i32,
 }
 
+#[derive(Default, Entity)]
+pub struct TestSelfRelation {
+    #[primary_key(increments)]
+    id: i32,
+    name: String,
+
+    #[relation(model = "Self", key = "id")]
+    parent_id: Option<i32>,
+}
+
+#[derive(Default, Entity)]
+#[polymorphic(name = "commentable", models = "TestEntity, TestOnlyPK")]
+pub struct TestPolymorphic {
+    #[primary_key(increments)]
+    id: i32,
+    body: String,
+
+    commentable_type: String,
+    commentable_id: i32,
+}
+
+#[derive(Partial)]
+#[partial_of(TestEntity)]
+pub struct TestEntityName {
+    id: i32,
+    name: String,
+}
+
+#[derive(Partial)]
+#[partial_of(TestCustomType)]
+pub struct TestCustomTypePartial {
+    #[custom_type(ty = "i32")]
+    my_enum: MyEnum,
+
+    #[field_ignore]
+    ignored: TestIgnoredType,
+}
+
+#[derive(FromRow)]
+pub struct TestReport {
+    name: String,
+    doubled: i32,
+
+    #[custom_type(ty = "i32")]
+    my_enum: MyEnum,
+
+    #[field_ignore]
+    ignored: TestIgnoredType,
+}
+
+#[derive(Default, Entity)]
+pub struct TestVersioned {
+    #[primary_key(increments)]
+    id: i32,
+    name: String,
+
+    #[version]
+    version: i32,
+}
+
+#[derive(Default, Entity)]
+pub struct TestVersionedTracked {
+    #[primary_key(increments)]
+    id: i32,
+    name: String,
+
+    #[version]
+    version: i64,
+
+    #[snapshot]
+    snapshot: Snapshot,
+}
+
+#[derive(Default, Entity)]
+pub struct TestTimestamps {
+    #[primary_key(increments)]
+    id: i32,
+    name: String,
+
+    #[created_at]
+    created_at: Option<DateTime<Utc>>,
+    #[updated_at]
+    updated_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Default, Entity)]
+pub struct TestSoftDelete {
+    #[primary_key(increments)]
+    id: i32,
+    name: String,
+
+    #[soft_delete]
+    deleted_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Default, Entity)]
+pub struct TestSoftDeleteRelation {
+    #[primary_key(increments)]
+    id: i32,
+
+    #[relation(model = "TestSoftDelete", key = "id")]
+    parent_id: i32,
+}
+
+#[derive(Partial)]
+#[partial_of(TestSoftDelete)]
+pub struct TestSoftDeleteName {
+    id: i32,
+    name: String,
+}
+
+#[derive(Default, Entity)]
+pub struct TestSoftDeleteTree {
+    #[primary_key(increments)]
+    id: i32,
+
+    #[relation(model = "Self", key = "id")]
+    parent_id: Option<i32>,
+
+    #[soft_delete]
+    deleted_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Default, Entity)]
+#[entity(hooks, audited, temporal)]
+pub struct TestHooks {
+    #[primary_key(increments)]
+    id: i32,
+    email: String,
+
+    #[soft_delete]
+    deleted_at: Option<DateTime<Utc>>,
+
+    #[field_ignore]
+    saved: i32,
+    #[field_ignore]
+    deleted: bool,
+    #[field_ignore]
+    loaded: bool,
+}
+
+#[async_trait]
+impl EntityHooks for TestHooks {
+    async fn before_save(&mut self, _db: &DB) -> DBResult<()> {
+        if self.email.is_empty() {
+            return Err(super::db::Error::Other("email is required".to_string()));
+        }
+        self.email = self.email.trim().to_lowercase();
+        Ok(())
+    }
+
+    async fn after_save(&mut self, _db: &DB) -> DBResult<()> {
+        self.saved += 1;
+        Ok(())
+    }
+
+    async fn before_delete(&mut self, _db: &DB) -> DBResult<()> {
+        if self.email == "protected@example.com" {
+            return Err(super::db::Error::Other("protected".to_string()));
+        }
+        Ok(())
+    }
+
+    async fn after_delete(&mut self, _db: &DB) -> DBResult<()> {
+        self.deleted = true;
+        Ok(())
+    }
+
+    async fn after_load(&mut self, _db: &DB) -> DBResult<()> {
+        self.loaded = true;
+        Ok(())
+    }
+}
+
+#[derive(Default, Entity)]
+#[entity(hooks)]
+pub struct TestHooksTracked {
+    #[primary_key(increments)]
+    id: i32,
+    name: String,
+
+    #[snapshot]
+    snapshot: Snapshot,
+
+    #[field_ignore]
+    saved: i32,
+}
+
+#[async_trait]
+impl EntityHooks for TestHooksTracked {
+    async fn after_save(&mut self, _db: &DB) -> DBResult<()> {
+        self.saved += 1;
+        Ok(())
+    }
+}
+
+fn validate_not_admin(name: &str) -> Result<(), String> {
+    match name {
+        "admin" => Err("is reserved".to_string()),
+        _ => Ok(()),
+    }
+}
+
+#[derive(Default, Entity)]
+pub struct TestValidated {
+    #[primary_key(increments)]
+    id: i32,
+
+    #[validate(length(min = 1, max = 8), custom = "validate_not_admin")]
+    name: String,
+
+    #[validate(email)]
+    email: Option<String>,
+
+    #[validate(range(min = 0, max = 100))]
+    score: i32,
+
+    #[validate(range(min = 0, max = 1))]
+    ratio: f64,
+}
+
+#[derive(Default, Entity)]
+pub struct TestTracked {
+    #[primary_key(increments)]
+    id: i32,
+    name: String,
+    email: String,
+
+    #[custom_type(ty = "i32")]
+    my_enum: MyEnum,
+
+    #[snapshot]
+    snapshot: Snapshot,
+}
+
 #[derive(Default)]
 pub struct TestIgnoredType {
     data: i32,
@@ -237,6 +468,57 @@ pub struct User {
     pub display_name: String,
 }
 
+#[derive(Entity, Default, Debug)]
+#[entity(audited)]
+pub struct TestAudited {
+    #[primary_key(increments)]
+    pub id: i32,
+    pub name: String,
+    pub balance: i64,
+    pub opened_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Entity, Default, Debug)]
+#[entity(temporal)]
+pub struct Table {
+    #[primary_key(increments)]
+    pub id: i32,
+    pub seats: i32,
+}
+
+#[derive(Entity, Default, Debug)]
+#[entity(emit_changes)]
+pub struct Order {
+    #[primary_key(increments)]
+    pub id: i32,
+    pub reference: String,
+}
+
+#[derive(Entity, Default, Debug)]
+#[entity(audited)]
+pub struct Group {
+    #[primary_key(increments)]
+    pub id: i32,
+    pub name: String,
+}
+
+#[derive(Entity, Default, Debug)]
+#[entity(temporal)]
+pub struct TestTemporal {
+    #[primary_key(increments)]
+    pub id: i32,
+    pub address: String,
+}
+
+#[derive(Entity, Default, Debug)]
+#[entity(emit_changes)]
+pub struct TestEmitChanges {
+    #[primary_key(increments)]
+    pub id: i32,
+    pub name: String,
+    pub notes: Option<String>,
+}
+
 #[tokio::test]
 async fn test_entity_macro_clean() {
     let _obj = TestEntity {
@@ -677,6 +959,1930 @@ async fn test_many_to_many() {
     assert_eq!(entity.id, loaded_entity[0].entity_id);
 }
 
+#[tokio::test]
+async fn test_self_relation() {
+    let db = super::db::test_utils::create_test_db("test_self_relation").await;
+
+    db.migrate_tables(&[TestSelfRelation::create_migration().unwrap()])
+        .await
+        .unwrap();
+
+    let mut root = TestSelfRelation {
+        name: "root".to_string(),
+        ..Default::default()
+    };
+    root.save(&db).await.unwrap();
+
+    let mut child = TestSelfRelation {
+        name: "child".to_string(),
+        ..Default::default()
+    };
+    child.set_parent(&db, &root).await.unwrap();
+
+    let mut grandchild = TestSelfRelation {
+        name: "grandchild".to_string(),
+        ..Default::default()
+    };
+    grandchild.set_parent(&db, &child).await.unwrap();
+
+    let mut sibling = TestSelfRelation {
+        name: "sibling".to_string(),
+        ..Default::default()
+    };
+    sibling.set_parent(&db, &root).await.unwrap();
+
+    assert!(root.get_parent(&db).await.is_err());
+    assert_eq!(child.id, grandchild.get_parent(&db).await.unwrap().id);
+
+    let ancestors = grandchild.ancestors(&db).await.unwrap();
+    assert_eq!(2, ancestors.len());
+    assert_eq!(child.id, ancestors[0].id);
+    assert_eq!(root.id, ancestors[1].id);
+
+    assert_eq!(0, root.ancestors(&db).await.unwrap().len());
+
+    let descendants = root.descendants(&db, None).await.unwrap();
+    assert_eq!(3, descendants.len());
+    assert_eq!(grandchild.id, descendants[2].id);
+
+    let descendants = root.descendants(&db, Some(1)).await.unwrap();
+    assert_eq!(2, descendants.len());
+    assert!(root.descendants(&db, Some(0)).await.unwrap().is_empty());
+
+    assert_eq!(0, grandchild.descendants(&db, None).await.unwrap().len());
+}
+
+#[tokio::test]
+async fn test_polymorphic() {
+    let db = super::db::test_utils::create_test_db("test_polymorphic").await;
+
+    db.migrate_tables(&[
+        TestEntity::create_migration().unwrap(),
+        TestOnlyPK::create_migration().unwrap(),
+        TestPolymorphic::create_migration().unwrap(),
+    ])
+    .await
+    .unwrap();
+
+    let mut entity = TestEntity::default();
+    entity.save(&db).await.unwrap();
+
+    let mut other = TestOnlyPK::default();
+    other.save(&db).await.unwrap();
+
+    let mut only_pk = TestOnlyPK::default();
+    only_pk.save(&db).await.unwrap();
+
+    let mut comment = TestPolymorphic::default();
+    assert!(comment.get_commentable(&db).await.is_err());
+    assert!(comment
+        .set_commentable(&db, &TestOnlyPK::default())
+        .await
+        .is_err());
+
+    comment.set_commentable(&db, &entity).await.unwrap();
+    assert_eq!(TestEntity::get_table_name(), comment.commentable_type);
+    match comment.get_commentable(&db).await.unwrap() {
+        TestPolymorphicCommentable::TestEntity(v) => assert_eq!(entity.id, v.id),
+        _ => panic!("expected a TestEntity"),
+    }
+
+    comment.set_commentable(&db, &only_pk).await.unwrap();
+    let loaded = TestPolymorphic::first(&db, "id = $1", &[&comment.id])
+        .await
+        .unwrap()
+        .unwrap();
+    match loaded.get_commentable(&db).await.unwrap() {
+        TestPolymorphicCommentable::TestOnlyPK(v) => assert_eq!(only_pk.id, v.id),
+        _ => panic!("expected a TestOnlyPK"),
+    }
+}
+
+#[tokio::test]
+async fn test_partial() {
+    let db = super::db::test_utils::create_test_db("test_partial").await;
+
+    db.migrate_tables(&[
+        TestEntity::create_migration().unwrap(),
+        TestCustomType::create_migration().unwrap(),
+    ])
+    .await
+    .unwrap();
+
+    assert_eq!(vec!["id", "name"], TestEntityName::get_columns());
+
+    for name in ["first", "second"].iter() {
+        let mut obj = TestEntity {
+            name: name.to_string(),
+            ..Default::default()
+        };
+        obj.save(&db).await.unwrap();
+    }
+
+    let results = TestEntityName::find(&db, "true", &[]).await.unwrap();
+    assert_eq!(2, results.len());
+    assert_eq!("first", results[0].name);
+
+    let result = TestEntityName::first(&db, "name = $1", &[&"second"])
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(2, result.id);
+
+    let result = TestEntityName::first(&db, "name = $1", &[&"third"])
+        .await
+        .unwrap();
+    assert!(result.is_none());
+
+    let mut obj = TestCustomType {
+        my_enum: MyEnum::Item2,
+        ..Default::default()
+    };
+    obj.save(&db).await.unwrap();
+
+    let result = TestCustomTypePartial::first(&db, "id = $1", &[&obj.id])
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(MyEnum::Item2, result.my_enum);
+}
+
+#[tokio::test]
+async fn test_from_row_query_as() {
+    let db = super::db::test_utils::create_test_db("test_from_row_query_as").await;
+
+    db.migrate_tables(&[TestEntity::create_migration().unwrap()])
+        .await
+        .unwrap();
+
+    for integer in 1..=2 {
+        let mut obj = TestEntity {
+            name: format!("test {}", integer),
+            integer,
+            ..Default::default()
+        };
+        obj.save(&db).await.unwrap();
+    }
+
+    let query = "SELECT name, integer * 2 AS doubled, 1 AS my_enum FROM test_entity ORDER BY id";
+    let results: Vec<TestReport> = db.query_as(query, &[]).await.unwrap();
+    assert_eq!(2, results.len());
+    assert_eq!("test 2", results[1].name);
+    assert_eq!(4, results[1].doubled);
+    assert_eq!(MyEnum::Item2, results[1].my_enum);
+
+    let query = "SELECT name, integer AS doubled, 0 AS my_enum FROM test_entity WHERE id = $1";
+    let result = db
+        .query_one_as::<TestReport>(query, &[&1])
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!("test 1", result.name);
+    assert_eq!(MyEnum::Item1, result.my_enum);
+
+    let result = db.query_one_as::<TestReport>(query, &[&3]).await.unwrap();
+    assert!(result.is_none());
+
+    let query = "SELECT name, integer AS doubled, 5 AS my_enum FROM test_entity";
+    assert!(db.query_as::<TestReport>(query, &[]).await.is_err());
+
+    // missing columns and mismatched types are errors, not panics
+    let query = "SELECT name, 1 AS my_enum FROM test_entity";
+    assert!(matches!(
+        db.query_as::<TestReport>(query, &[]).await,
+        Err(super::db::Error::PostgresError(_))
+    ));
+    let query = "SELECT name, 'two' AS doubled, 1 AS my_enum FROM test_entity";
+    assert!(matches!(
+        db.query_as::<TestReport>(query, &[]).await,
+        Err(super::db::Error::PostgresError(_))
+    ));
+}
+
+#[tokio::test]
+async fn test_aggregates() {
+    let db = super::db::test_utils::create_test_db("test_aggregates").await;
+
+    db.migrate_tables(&[TestEntity::create_migration().unwrap()])
+        .await
+        .unwrap();
+
+    assert_eq!(0, TestEntity::count(&db, "true", &[]).await.unwrap());
+    assert!(!TestEntity::exists(&db, "true", &[]).await.unwrap());
+    let sum: Option<i64> = TestEntity::sum(&db, "integer", "true", &[]).await.unwrap();
+    assert!(sum.is_none());
+
+    for (name, integer) in [("open", 1), ("open", 2), ("closed", 6)].iter() {
+        let mut obj = TestEntity {
+            name: name.to_string(),
+            integer: *integer,
+            ..Default::default()
+        };
+        obj.save(&db).await.unwrap();
+    }
+
+    assert_eq!(3, TestEntity::count(&db, "true", &[]).await.unwrap());
+    assert_eq!(
+        2,
+        TestEntity::count(&db, "name = $1", &[&"open"])
+            .await
+            .unwrap()
+    );
+    assert!(TestEntity::exists(&db, "integer > $1", &[&5])
+        .await
+        .unwrap());
+    assert!(!TestEntity::exists(&db, "integer > $1", &[&6])
+        .await
+        .unwrap());
+
+    let sum: Option<i64> = TestEntity::sum(&db, "integer", "true", &[]).await.unwrap();
+    assert_eq!(Some(9), sum);
+    let avg = TestEntity::avg(&db, "integer", "name = $1", &[&"open"])
+        .await
+        .unwrap();
+    assert_eq!(Some(1.5), avg);
+    let min: Option<i32> = TestEntity::min(&db, "integer", "true", &[]).await.unwrap();
+    assert_eq!(Some(1), min);
+    let max: Option<String> = TestEntity::max(&db, "name", "true", &[]).await.unwrap();
+    assert_eq!(Some("open".to_string()), max);
+
+    let counts: Vec<(String, i64)> = TestEntity::group_by(&db, "name", "COUNT(*)", "true", &[])
+        .await
+        .unwrap();
+    assert_eq!(
+        vec![("closed".to_string(), 1), ("open".to_string(), 2)],
+        counts
+    );
+
+    let totals: Vec<(String, i64, i32)> = TestEntity::group_by(
+        &db,
+        "name",
+        "SUM(integer), MAX(integer)",
+        "integer < $1",
+        &[&6],
+    )
+    .await
+    .unwrap();
+    assert_eq!(vec![("open".to_string(), 3, 2)], totals);
+}
+
+#[tokio::test]
+async fn test_dirty_tracking() {
+    let db = super::db::test_utils::create_test_db("test_dirty_tracking").await;
+
+    db.migrate_tables(&[TestTracked::create_migration().unwrap()])
+        .await
+        .unwrap();
+
+    let mut obj = TestTracked {
+        name: "name".to_string(),
+        email: "me@example.com".to_string(),
+        ..Default::default()
+    };
+    assert_eq!(vec!["name", "email", "my_enum"], obj.get_dirty_fields());
+    assert!(obj.save(&db).await.unwrap());
+    assert!(obj.get_dirty_fields().is_empty());
+
+    // another writer changes the email behind our back
+    let query = "UPDATE test_tracked SET email = $1 WHERE id = $2";
+    db.execute(query, &[&"other@example.com", &obj.id])
+        .await
+        .unwrap();
+
+    // clean entities are not written at all
+    assert!(!obj.save(&db).await.unwrap());
+
+    obj.name = "changed".to_string();
+    obj.my_enum = MyEnum::Item2;
+    assert_eq!(vec!["name", "my_enum"], obj.get_dirty_fields());
+    assert!(!obj.save(&db).await.unwrap());
+    assert!(obj.get_dirty_fields().is_empty());
+
+    let mut loaded = TestTracked::first(&db, "id = $1", &[&obj.id])
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!("changed", loaded.name);
+    assert_eq!("other@example.com", loaded.email);
+    assert_eq!(MyEnum::Item2, loaded.my_enum);
+    assert!(loaded.get_dirty_fields().is_empty());
+
+    loaded.email = "new@example.com".to_string();
+    assert_eq!(vec!["email"], loaded.get_dirty_fields());
+    assert!(!loaded.save(&db).await.unwrap());
+
+    assert!(loaded.delete(&db).await.unwrap());
+    assert_eq!(3, loaded.get_dirty_fields().len());
+    assert!(loaded.save(&db).await.unwrap());
+    assert_eq!(1, TestTracked::count(&db, "true", &[]).await.unwrap());
+
+    // a row deleted behind our back is not re-created by saving the changed columns
+    let query = "DELETE FROM test_tracked WHERE id = $1";
+    db.execute(query, &[&loaded.id]).await.unwrap();
+    loaded.name = "deleted".to_string();
+    assert!(matches!(
+        loaded.save(&db).await,
+        Err(super::db::Error::DoesNotExist)
+    ));
+    assert_eq!(0, TestTracked::count(&db, "true", &[]).await.unwrap());
+}
+
+#[tokio::test]
+async fn test_insert_update() {
+    let db = super::db::test_utils::create_test_db("test_insert_update").await;
+
+    db.migrate_tables(&[
+        TestCustomPrimaryKey::create_migration().unwrap(),
+        TestEntity::create_migration().unwrap(),
+    ])
+    .await
+    .unwrap();
+
+    let mut obj = TestCustomPrimaryKey {
+        name: "hello".to_string(),
+        email: "world".to_string(),
+    };
+    obj.insert(&db).await.unwrap();
+
+    let mut duplicate = TestCustomPrimaryKey {
+        name: "hello".to_string(),
+        email: "other".to_string(),
+    };
+    assert!(matches!(
+        duplicate.insert(&db).await,
+        Err(super::db::Error::AlreadyExists)
+    ));
+
+    obj.email = "updated".to_string();
+    obj.update(&db).await.unwrap();
+    let result = TestCustomPrimaryKey::first(&db, "name = $1", &[&obj.name])
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(result.email, "updated");
+
+    let mut typo = TestCustomPrimaryKey {
+        name: "helo".to_string(),
+        email: "world".to_string(),
+    };
+    assert!(matches!(
+        typo.update(&db).await,
+        Err(super::db::Error::DoesNotExist)
+    ));
+    assert_eq!(
+        TestCustomPrimaryKey::count(&db, "true", &[]).await.unwrap(),
+        1
+    );
+
+    let mut entity = TestEntity {
+        name: "test".to_string(),
+        ..Default::default()
+    };
+    entity.insert(&db).await.unwrap();
+    assert_eq!(entity.id, 1);
+}
+
+#[tokio::test]
+async fn test_optimistic_locking() {
+    let db = super::db::test_utils::create_test_db("test_optimistic_locking").await;
+
+    db.migrate_tables(&[
+        TestVersioned::create_migration().unwrap(),
+        TestVersionedTracked::create_migration().unwrap(),
+    ])
+    .await
+    .unwrap();
+
+    db.execute("INSERT INTO test_versioned (name) values ('default')", &[])
+        .await
+        .unwrap();
+    let result = TestVersioned::first(&db, "name = $1", &[&"default"])
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(result.version, 0);
+
+    let mut obj = TestVersioned {
+        name: "first".to_string(),
+        ..Default::default()
+    };
+    assert!(obj.save(&db).await.unwrap());
+    assert_eq!(obj.version, 0);
+
+    let mut a = TestVersioned::first(&db, "id = $1", &[&obj.id])
+        .await
+        .unwrap()
+        .unwrap();
+    let mut b = TestVersioned::first(&db, "id = $1", &[&obj.id])
+        .await
+        .unwrap()
+        .unwrap();
+
+    a.name = "a".to_string();
+    a.save(&db).await.unwrap();
+    assert_eq!(a.version, 1);
+
+    b.name = "b".to_string();
+    assert!(matches!(
+        b.save(&db).await,
+        Err(super::db::Error::StaleEntity)
+    ));
+    assert!(matches!(
+        b.update(&db).await,
+        Err(super::db::Error::StaleEntity)
+    ));
+
+    a.name = "a2".to_string();
+    a.update(&db).await.unwrap();
+    assert_eq!(a.version, 2);
+
+    let mut tracked = TestVersionedTracked {
+        name: "tracked".to_string(),
+        ..Default::default()
+    };
+    tracked.save(&db).await.unwrap();
+
+    let mut stale = TestVersionedTracked::first(&db, "id = $1", &[&tracked.id])
+        .await
+        .unwrap()
+        .unwrap();
+
+    tracked.name = "changed".to_string();
+    tracked.save(&db).await.unwrap();
+    assert_eq!(tracked.version, 1);
+
+    stale.name = "stale".to_string();
+    assert!(matches!(
+        stale.save(&db).await,
+        Err(super::db::Error::StaleEntity)
+    ));
+
+    let result = TestVersionedTracked::first(&db, "id = $1", &[&tracked.id])
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(result.name, "changed");
+    assert_eq!(result.version, 1);
+}
+
+#[tokio::test]
+async fn test_timestamps() {
+    let db = super::db::test_utils::create_test_db("test_timestamps").await;
+
+    db.migrate_tables(&[TestTimestamps::create_migration().unwrap()])
+        .await
+        .unwrap();
+
+    let mut obj = TestTimestamps {
+        name: "first".to_string(),
+        ..Default::default()
+    };
+    obj.save(&db).await.unwrap();
+    let created_at = obj.created_at.unwrap();
+    let updated_at = obj.updated_at.unwrap();
+    assert_eq!(created_at, updated_at);
+
+    obj.name = "second".to_string();
+    obj.save(&db).await.unwrap();
+    assert_eq!(obj.created_at.unwrap(), created_at);
+    assert!(obj.updated_at.unwrap() > updated_at);
+    let updated_at = obj.updated_at.unwrap();
+
+    obj.created_at = None;
+    obj.update(&db).await.unwrap();
+    assert_eq!(obj.created_at.unwrap(), created_at);
+    assert!(obj.updated_at.unwrap() > updated_at);
+
+    let mut inserted = TestTimestamps {
+        name: "inserted".to_string(),
+        ..Default::default()
+    };
+    inserted.insert(&db).await.unwrap();
+    assert!(inserted.created_at.unwrap() > created_at);
+
+    db.execute("INSERT INTO test_timestamps (name) values ('raw')", &[])
+        .await
+        .unwrap();
+    let result = TestTimestamps::first(&db, "name = $1", &[&"raw"])
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(result.created_at.is_some());
+    assert!(result.updated_at.is_some());
+}
+
+#[tokio::test]
+async fn test_soft_delete() {
+    let db = super::db::test_utils::create_test_db("test_soft_delete").await;
+
+    db.migrate_tables(&[
+        TestSoftDelete::create_migration().unwrap(),
+        TestSoftDeleteRelation::create_migration().unwrap(),
+        TestSoftDeleteTree::create_migration().unwrap(),
+    ])
+    .await
+    .unwrap();
+
+    let mut obj = TestSoftDelete {
+        name: "trashed".to_string(),
+        ..Default::default()
+    };
+    obj.save(&db).await.unwrap();
+    let mut other = TestSoftDelete {
+        name: "kept".to_string(),
+        ..Default::default()
+    };
+    other.save(&db).await.unwrap();
+
+    let mut relation = TestSoftDeleteRelation {
+        parent_id: obj.id,
+        ..Default::default()
+    };
+    relation.save(&db).await.unwrap();
+    assert_eq!(relation.get_test_soft_delete(&db).await.unwrap().id, obj.id);
+
+    assert!(obj.delete(&db).await.unwrap());
+    assert!(obj.deleted_at.is_some());
+    assert!(obj.is_synced_with_db());
+    assert!(!obj.delete(&db).await.unwrap());
+
+    assert_eq!(
+        TestSoftDelete::find(&db, "true", &[]).await.unwrap().len(),
+        1
+    );
+    assert!(TestSoftDelete::first(&db, "id = $1", &[&obj.id])
+        .await
+        .unwrap()
+        .is_none());
+    assert_eq!(TestSoftDelete::count(&db, "true", &[]).await.unwrap(), 1);
+    assert!(relation.get_test_soft_delete(&db).await.is_err());
+
+    let names = TestSoftDeleteName::find(&db, "true", &[]).await.unwrap();
+    assert_eq!(1, names.len());
+    assert_eq!(other.id, names[0].id);
+    assert_eq!("kept", names[0].name);
+    assert!(TestSoftDeleteName::first(&db, "id = $1", &[&obj.id])
+        .await
+        .unwrap()
+        .is_none());
+
+    assert_eq!(
+        TestSoftDelete::with_trashed()
+            .find(&db, "true", &[])
+            .await
+            .unwrap()
+            .len(),
+        2
+    );
+    let trashed = TestSoftDelete::only_trashed()
+        .first(&db, "true", &[])
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(trashed.id, obj.id);
+    assert_eq!(
+        TestSoftDelete::only_trashed()
+            .count(&db, "true", &[])
+            .await
+            .unwrap(),
+        1
+    );
+
+    assert!(obj.restore(&db).await.unwrap());
+    assert!(obj.deleted_at.is_none());
+    assert!(!obj.restore(&db).await.unwrap());
+    assert_eq!(TestSoftDelete::count(&db, "true", &[]).await.unwrap(), 2);
+
+    relation.delete(&db).await.unwrap();
+    assert!(obj.force_delete(&db).await.unwrap());
+    assert!(!obj.is_synced_with_db());
+    assert_eq!(
+        TestSoftDelete::with_trashed()
+            .count(&db, "true", &[])
+            .await
+            .unwrap(),
+        1
+    );
+
+    // trashed nodes cut their branch of the tree
+    let mut root = TestSoftDeleteTree::default();
+    root.save(&db).await.unwrap();
+    let mut child = TestSoftDeleteTree::default();
+    child.set_parent(&db, &root).await.unwrap();
+    let mut grandchild = TestSoftDeleteTree::default();
+    grandchild.set_parent(&db, &child).await.unwrap();
+    assert_eq!(2, root.descendants(&db, None).await.unwrap().len());
+    assert_eq!(2, grandchild.ancestors(&db).await.unwrap().len());
+
+    assert!(root.delete(&db).await.unwrap());
+    let ancestors = grandchild.ancestors(&db).await.unwrap();
+    assert_eq!(1, ancestors.len());
+    assert_eq!(child.id, ancestors[0].id);
+
+    assert!(root.restore(&db).await.unwrap());
+    assert!(child.delete(&db).await.unwrap());
+    assert!(root.descendants(&db, None).await.unwrap().is_empty());
+    assert!(grandchild.ancestors(&db).await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_hooks() {
+    let db = super::db::test_utils::create_test_db("test_hooks").await;
+
+    db.migrate_tables(&[TestHooks::create_migration().unwrap()])
+        .await
+        .unwrap();
+
+    let mut invalid = TestHooks::default();
+    assert!(invalid.save(&db).await.is_err());
+    assert!(invalid.insert(&db).await.is_err());
+    assert_eq!(invalid.saved, 0);
+    assert_eq!(TestHooks::count(&db, "true", &[]).await.unwrap(), 0);
+
+    let mut obj = TestHooks {
+        email: " Protected@Example.com ".to_string(),
+        ..Default::default()
+    };
+    obj.save(&db).await.unwrap();
+    assert_eq!(obj.email, "protected@example.com");
+    assert_eq!(obj.saved, 1);
+
+    obj.update(&db).await.unwrap();
+    assert_eq!(obj.saved, 2);
+
+    let mut loaded = TestHooks::first(&db, "id = $1", &[&obj.id])
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(loaded.loaded);
+    assert_eq!(loaded.email, "protected@example.com");
+    assert!(TestHooks::find(&db, "true", &[]).await.unwrap()[0].loaded);
+
+    assert!(loaded.delete(&db).await.is_err());
+    assert!(!loaded.deleted);
+    assert_eq!(TestHooks::count(&db, "true", &[]).await.unwrap(), 1);
+
+    loaded.email = "other@example.com".to_string();
+    loaded.save(&db).await.unwrap();
+    assert!(loaded.delete(&db).await.unwrap());
+    assert!(loaded.deleted);
+    assert_eq!(TestHooks::count(&db, "true", &[]).await.unwrap(), 0);
+
+    // every loader runs after_load
+    let trashed = TestHooks::only_trashed()
+        .first(&db, "true", &[])
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(trashed.loaded);
+    assert!(
+        TestHooks::with_trashed()
+            .find(&db, "true", &[])
+            .await
+            .unwrap()[0]
+            .loaded
+    );
+
+    let history = trashed.history(&db).await.unwrap();
+    assert_eq!(4, history.len());
+    for entry in history {
+        assert!(entry
+            .before
+            .iter()
+            .chain(entry.after.iter())
+            .all(|s| s.loaded));
+    }
+
+    let versions = TestHooks::as_of(&db, Utc::now()).await.unwrap();
+    assert_eq!(1, versions.len());
+    assert!(versions[0].loaded);
+}
+
+#[tokio::test]
+async fn test_hooks_unchanged_save() {
+    let db = super::db::test_utils::create_test_db("test_hooks_unchanged_save").await;
+
+    db.migrate_tables(&[TestHooksTracked::create_migration().unwrap()])
+        .await
+        .unwrap();
+
+    let mut obj = TestHooksTracked {
+        name: "first".to_string(),
+        ..Default::default()
+    };
+    assert!(obj.save(&db).await.unwrap());
+    assert_eq!(1, obj.saved);
+
+    // nothing is written by a clean entity, so after_save does not run
+    assert!(!obj.save(&db).await.unwrap());
+    assert_eq!(1, obj.saved);
+
+    obj.name = "second".to_string();
+    assert!(!obj.save(&db).await.unwrap());
+    assert_eq!(2, obj.saved);
+}
+
+#[tokio::test]
+async fn test_validation() {
+    let db = super::db::test_utils::create_test_db("test_validation").await;
+
+    db.migrate_tables(&[TestValidated::create_migration().unwrap()])
+        .await
+        .unwrap();
+
+    let mut obj = TestValidated {
+        name: "too long name".to_string(),
+        email: Some("not an email".to_string()),
+        score: -1,
+        ratio: -0.5,
+        ..Default::default()
+    };
+    let fields = match obj.save(&db).await {
+        Err(super::db::Error::Validation(errors)) => {
+            errors.into_iter().map(|e| e.field).collect::<Vec<String>>()
+        }
+        _ => panic!("expected a validation error"),
+    };
+    assert_eq!(fields, vec!["name", "email", "score", "ratio"]);
+    assert_eq!(TestValidated::count(&db, "true", &[]).await.unwrap(), 0);
+
+    obj.name = "admin".to_string();
+    obj.email = None;
+    obj.score = 101;
+    obj.ratio = 1.5;
+    match obj.insert(&db).await {
+        Err(super::db::Error::Validation(errors)) => {
+            assert_eq!(errors.len(), 3);
+            assert_eq!(errors[0], FieldError::new("name", "is reserved"));
+            assert_eq!(errors[1].field, "score");
+            assert_eq!(errors[2], FieldError::new("ratio", "must be at most 1"));
+        }
+        _ => panic!("expected a validation error"),
+    }
+
+    obj.name = "valid".to_string();
+    obj.email = Some("valid@example.com".to_string());
+    obj.score = 100;
+    obj.ratio = 0.5;
+    obj.save(&db).await.unwrap();
+
+    obj.name = "".to_string();
+    assert!(matches!(
+        obj.update(&db).await,
+        Err(super::db::Error::Validation(_))
+    ));
+}
+
+#[tokio::test]
+async fn test_insert_many() {
+    let db = super::db::test_utils::create_test_db("test_insert_many").await;
+
+    db.migrate_tables(&[
+        TestEntity::create_migration().unwrap(),
+        TestTracked::create_migration().unwrap(),
+        TestVersioned::create_migration().unwrap(),
+        TestOnlyPK::create_migration().unwrap(),
+    ])
+    .await
+    .unwrap();
+
+    // more rows than fit in a single statement
+    let mut entities: Vec<TestEntity> = (0..10000)
+        .map(|i| TestEntity {
+            name: format!("entity {}", i),
+            integer: i,
+            ..Default::default()
+        })
+        .collect();
+    TestEntity::insert_many(&db, &mut entities).await.unwrap();
+    assert_eq!(entities[0].id, 1);
+    assert_eq!(entities[9999].id, 10000);
+    assert_eq!(TestEntity::count(&db, "true", &[]).await.unwrap(), 10000);
+    let loaded = TestEntity::first(&db, "id = $1", &[&entities[8500].id])
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(loaded.integer, 8500);
+
+    assert!(TestEntity::insert_many(&db, &mut entities[..1])
+        .await
+        .is_err());
+
+    // a failing chunk rolls back the ones already written
+    let mut entities: Vec<TestEntity> = (0..10000)
+        .map(|i| TestEntity {
+            id: if i == 9999 { 1 } else { 0 },
+            name: format!("duplicate {}", i),
+            ..Default::default()
+        })
+        .collect();
+    assert!(TestEntity::insert_many(&db, &mut entities).await.is_err());
+    assert_eq!(TestEntity::count(&db, "true", &[]).await.unwrap(), 10000);
+
+    let mut tracked = vec![
+        TestTracked {
+            name: "first".to_string(),
+            my_enum: MyEnum::Item2,
+            ..Default::default()
+        },
+        TestTracked {
+            name: "second".to_string(),
+            ..Default::default()
+        },
+    ];
+    TestTracked::insert_many(&db, &mut tracked).await.unwrap();
+    assert_eq!(tracked[1].id, 2);
+    assert!(tracked[0].get_dirty_fields().is_empty());
+
+    tracked[0].name = "first updated".to_string();
+    tracked.push(TestTracked {
+        name: "third".to_string(),
+        ..Default::default()
+    });
+    TestTracked::upsert_many(&db, &mut tracked).await.unwrap();
+    assert_eq!(tracked[2].id, 3);
+    let loaded = TestTracked::first(&db, "id = $1", &[&tracked[0].id])
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(loaded.name, "first updated");
+    assert!(matches!(loaded.my_enum, MyEnum::Item2));
+
+    let mut versioned = vec![TestVersioned {
+        name: "versioned".to_string(),
+        ..Default::default()
+    }];
+    TestVersioned::upsert_many(&db, &mut versioned)
+        .await
+        .unwrap();
+    TestVersioned::upsert_many(&db, &mut versioned)
+        .await
+        .unwrap();
+    assert_eq!(versioned[0].version, 1);
+    versioned[0].version = 0;
+    assert!(matches!(
+        TestVersioned::upsert_many(&db, &mut versioned).await,
+        Err(super::db::Error::StaleEntity)
+    ));
+
+    // rows left untouched are not returned, the others still get their keys
+    let mut only_pk = vec![TestOnlyPK::default()];
+    TestOnlyPK::insert_many(&db, &mut only_pk).await.unwrap();
+    only_pk.push(TestOnlyPK::default());
+    TestOnlyPK::upsert_many(&db, &mut only_pk).await.unwrap();
+    assert_eq!(only_pk[0].id, 1);
+    assert_eq!(only_pk[1].id, 2);
+    assert_eq!(TestOnlyPK::count(&db, "true", &[]).await.unwrap(), 2);
+}
+
+#[tokio::test]
+async fn test_update_delete_where() {
+    let db = super::db::test_utils::create_test_db("test_update_delete_where").await;
+
+    db.migrate_tables(&[
+        TestEntity::create_migration().unwrap(),
+        TestVersioned::create_migration().unwrap(),
+        TestSoftDelete::create_migration().unwrap(),
+    ])
+    .await
+    .unwrap();
+
+    let mut entities: Vec<TestEntity> = (0..10)
+        .map(|i| TestEntity {
+            name: format!("entity {}", i),
+            integer: i,
+            ..Default::default()
+        })
+        .collect();
+    TestEntity::insert_many(&db, &mut entities).await.unwrap();
+
+    let updated = TestEntity::update_where(&db, "integer < $1", "boolean = $2", &[&5, &true])
+        .await
+        .unwrap();
+    assert_eq!(updated, 5);
+    assert_eq!(
+        TestEntity::count(&db, "boolean = $1", &[&true])
+            .await
+            .unwrap(),
+        5
+    );
+
+    let updated =
+        TestEntity::update_where_returning(&db, "integer = $1", "name = $2", &[&7, &"seven"])
+            .await
+            .unwrap();
+    assert_eq!(updated.len(), 1);
+    assert_eq!(updated[0].name, "seven");
+    assert_eq!(updated[0].id, entities[7].id);
+
+    let deleted = TestEntity::delete_where(&db, "boolean = $1", &[&true])
+        .await
+        .unwrap();
+    assert_eq!(deleted, 5);
+
+    let deleted = TestEntity::delete_where_returning(&db, "integer >= $1", &[&8])
+        .await
+        .unwrap();
+    assert_eq!(deleted.len(), 2);
+    assert_eq!(TestEntity::count(&db, "true", &[]).await.unwrap(), 3);
+
+    let mut versioned = TestVersioned {
+        name: "versioned".to_string(),
+        ..Default::default()
+    };
+    versioned.insert(&db).await.unwrap();
+    TestVersioned::update_where(&db, "true", "name = $1", &[&"renamed"])
+        .await
+        .unwrap();
+    versioned.name = "stale".to_string();
+    assert!(matches!(
+        versioned.update(&db).await,
+        Err(super::db::Error::StaleEntity)
+    ));
+
+    let mut soft = vec![
+        TestSoftDelete {
+            name: "kept".to_string(),
+            ..Default::default()
+        },
+        TestSoftDelete {
+            name: "trashed".to_string(),
+            ..Default::default()
+        },
+    ];
+    TestSoftDelete::insert_many(&db, &mut soft).await.unwrap();
+    let trashed = TestSoftDelete::delete_where_returning(&db, "name = $1", &[&"trashed"])
+        .await
+        .unwrap();
+    assert_eq!(trashed.len(), 1);
+    assert!(trashed[0].deleted_at.is_some());
+    assert_eq!(TestSoftDelete::count(&db, "true", &[]).await.unwrap(), 1);
+    assert_eq!(
+        TestSoftDelete::with_trashed()
+            .count(&db, "true", &[])
+            .await
+            .unwrap(),
+        2
+    );
+
+    // trashed rows are neither updated nor trashed again
+    let updated = TestSoftDelete::update_where(&db, "true", "name = $1", &[&"all"])
+        .await
+        .unwrap();
+    assert_eq!(updated, 1);
+    let deleted = TestSoftDelete::delete_where(&db, "true", &[])
+        .await
+        .unwrap();
+    assert_eq!(deleted, 1);
+}
+
+#[tokio::test]
+async fn test_find_stream() {
+    use futures::TryStreamExt;
+
+    let db = super::db::test_utils::create_test_db("test_find_stream").await;
+
+    db.migrate_tables(&[
+        TestEntity::create_migration().unwrap(),
+        TestHooks::create_migration().unwrap(),
+    ])
+    .await
+    .unwrap();
+
+    let mut entities: Vec<TestEntity> = (0..1200)
+        .map(|i| TestEntity {
+            name: format!("entity {}", i),
+            integer: i,
+            ..Default::default()
+        })
+        .collect();
+    TestEntity::insert_many(&db, &mut entities).await.unwrap();
+
+    let streamed: Vec<TestEntity> = TestEntity::find_stream(&db, "integer >= $1", &[&200])
+        .await
+        .unwrap()
+        .try_collect()
+        .await
+        .unwrap();
+    assert_eq!(streamed.len(), 1000);
+
+    let tx = db.transaction().await.unwrap();
+    let mut stream = TestEntity::find_stream(&tx, "true", &[]).await.unwrap();
+    let mut count = 0;
+    while let Some(entity) = stream.try_next().await.unwrap() {
+        assert_eq!(entity.name, format!("entity {}", entity.integer));
+        count += 1;
+    }
+    assert_eq!(count, 1200);
+    drop(stream);
+    tx.commit().await.unwrap();
+
+    let mut obj = TestHooks {
+        email: "stream@example.com".to_string(),
+        ..Default::default()
+    };
+    obj.save(&db).await.unwrap();
+    let loaded: Vec<TestHooks> = TestHooks::find_stream(&db, "true", &[])
+        .await
+        .unwrap()
+        .try_collect()
+        .await
+        .unwrap();
+    assert!(loaded[0].loaded);
+}
+
+#[tokio::test]
+async fn test_query_pagination() {
+    let db = super::db::test_utils::create_test_db("test_query_pagination").await;
+
+    db.migrate_tables(&[
+        TestEntity::create_migration().unwrap(),
+        TestSoftDelete::create_migration().unwrap(),
+    ])
+    .await
+    .unwrap();
+
+    let mut entities: Vec<TestEntity> = (0..45)
+        .map(|i| TestEntity {
+            name: format!("entity {}", i),
+            integer: i % 10,
+            ..Default::default()
+        })
+        .collect();
+    TestEntity::insert_many(&db, &mut entities).await.unwrap();
+
+    let found = TestEntity::query()
+        .filter("integer >= $1", &[&5])
+        .filter("integer < $1 AND name <> '$1'", &[&7])
+        .order_by("id DESC")
+        .limit(3)
+        .find(&db)
+        .await
+        .unwrap();
+    assert_eq!(found.len(), 3);
+    assert_eq!(found[0].id, 37);
+    assert!(found.iter().all(|e| e.integer == 5 || e.integer == 6));
+
+    let first = TestEntity::query()
+        .filter("integer = $1", &[&9])
+        .order_by("id")
+        .first(&db)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(first.id, 10);
+
+    let page = TestEntity::query()
+        .order_by("id")
+        .paginate(&db, 3, 20)
+        .await
+        .unwrap();
+    assert_eq!(page.total, 45);
+    assert_eq!(page.total_pages(), 3);
+    assert_eq!(page.items.len(), 5);
+    assert_eq!(page.items[0].id, 41);
+
+    // keyset pages through rows sharing the same sort value
+    let mut seen = vec![];
+    let mut cursor: Option<String> = None;
+    loop {
+        let mut query = TestEntity::query()
+            .filter("integer < $1", &[&8])
+            .keyset_desc("integer")
+            .limit(7);
+        if let Some(encoded) = &cursor {
+            query = query.after(&Cursor::decode(encoded).unwrap());
+        }
+        let page = query.find_page(&db).await.unwrap();
+        seen.extend(page.items.iter().map(|e| (e.integer, e.id)));
+        match page.next {
+            Some(next) => cursor = Some(next.encode()),
+            None => break,
+        }
+    }
+    assert_eq!(seen.len(), 37);
+    let mut sorted = seen.clone();
+    sorted.sort_by(|a, b| b.cmp(a));
+    assert_eq!(seen, sorted);
+
+    let all = TestEntity::query().find_page(&db).await.unwrap();
+    assert_eq!(all.items.len(), 45);
+    assert!(all.next.is_none());
+
+    let unbounded = TestEntity::query()
+        .limit(i64::MAX)
+        .find_page(&db)
+        .await
+        .unwrap();
+    assert_eq!(unbounded.items.len(), 45);
+    assert!(unbounded.next.is_none());
+
+    assert!(Cursor::decode("zz").is_err());
+
+    // the types of a cursor end up in the query, forged ones are rejected
+    let forged: String = "int4); DROP TABLE test_entity; --:1"
+        .bytes()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    assert!(matches!(
+        Cursor::decode(&forged),
+        Err(super::db::Error::Other(_))
+    ));
+
+    let page = TestEntity::query()
+        .order_by("id")
+        .paginate(&db, 0, 20)
+        .await
+        .unwrap();
+    assert_eq!(page.page, 1);
+    assert_eq!(page.items[0].id, 1);
+    assert!(TestEntity::query()
+        .paginate(&db, i64::MAX, 20)
+        .await
+        .is_err());
+
+    let mut trashed = TestSoftDelete {
+        name: "trashed".to_string(),
+        ..Default::default()
+    };
+    trashed.save(&db).await.unwrap();
+    trashed.delete(&db).await.unwrap();
+    assert_eq!(TestSoftDelete::query().count(&db).await.unwrap(), 0);
+}
+
+#[tokio::test]
+async fn test_find_by() {
+    let db = super::db::test_utils::create_test_db("test_find_by").await;
+
+    db.migrate_tables(&[
+        TestEntity::create_migration().unwrap(),
+        TestCustomIndexes::create_migration().unwrap(),
+        TestTracked::create_migration().unwrap(),
+    ])
+    .await
+    .unwrap();
+
+    let mut entity = TestEntity {
+        name: "first".to_string(),
+        integer: 7,
+        ..Default::default()
+    };
+    entity.save(&db).await.unwrap();
+    let mut other = TestEntity {
+        name: "second".to_string(),
+        integer: 7,
+        ..Default::default()
+    };
+    other.save(&db).await.unwrap();
+
+    let found = TestEntity::find_by_pk(&db, &entity.id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(found.name, "first");
+    assert!(TestEntity::find_by_pk(&db, &1000).await.unwrap().is_none());
+    assert!(matches!(
+        TestEntity::get_by_pk(&db, &1000).await,
+        Err(super::db::Error::DoesNotExist)
+    ));
+    assert_eq!(
+        TestEntity::get_by_pk(&db, &other.id).await.unwrap().name,
+        "second"
+    );
+
+    assert_eq!(TestEntity::find_by_integer(&db, &7).await.unwrap().len(), 2);
+    assert!(TestEntity::find_by_integer(&db, &8)
+        .await
+        .unwrap()
+        .is_empty());
+
+    let mut indexed = TestCustomIndexes {
+        name: "name".to_string(),
+        date: "date".to_string(),
+        email: "me@example.com".to_string(),
+        ..Default::default()
+    };
+    indexed.save(&db).await.unwrap();
+    let found = TestCustomIndexes::find_by_email(&db, &"me@example.com".to_string())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(found.id, indexed.id);
+
+    TestEntity::update_where(&db, "id = $1", "name = $2", &[&entity.id, &"renamed"])
+        .await
+        .unwrap();
+    entity.reload(&db).await.unwrap();
+    assert_eq!(entity.name, "renamed");
+
+    let mut tracked = TestTracked {
+        name: "tracked".to_string(),
+        ..Default::default()
+    };
+    tracked.save(&db).await.unwrap();
+    TestTracked::update_where(&db, "id = $1", "my_enum = $2", &[&tracked.id, &1])
+        .await
+        .unwrap();
+    tracked.reload(&db).await.unwrap();
+    assert_eq!(tracked.my_enum, MyEnum::Item2);
+    assert!(tracked.get_dirty_fields().is_empty());
+
+    entity.delete(&db).await.unwrap();
+    assert!(matches!(
+        entity.reload(&db).await,
+        Err(super::db::Error::DoesNotExist)
+    ));
+}
+
+#[tokio::test]
+async fn test_query_locks() {
+    let db = super::db::test_utils::create_test_db("test_query_locks").await;
+
+    db.migrate_tables(&[TestEntity::create_migration().unwrap()])
+        .await
+        .unwrap();
+
+    let mut entities: Vec<TestEntity> = (0..5)
+        .map(|i| TestEntity {
+            name: "pending".to_string(),
+            integer: i,
+            ..Default::default()
+        })
+        .collect();
+    TestEntity::insert_many(&db, &mut entities).await.unwrap();
+
+    let first_worker = db.transaction().await.unwrap();
+    let claimed = TestEntity::query()
+        .filter("name = $1", &[&"pending"])
+        .order_by("id")
+        .for_update()
+        .skip_locked()
+        .limit(2)
+        .find(&first_worker)
+        .await
+        .unwrap();
+    assert_eq!(
+        claimed.iter().map(|e| e.id).collect::<Vec<i32>>(),
+        vec![1, 2]
+    );
+
+    let second_worker = db.transaction().await.unwrap();
+    let claimed = TestEntity::query()
+        .filter("name = $1", &[&"pending"])
+        .order_by("id")
+        .skip_locked()
+        .limit(2)
+        .find(&second_worker)
+        .await
+        .unwrap();
+    assert_eq!(
+        claimed.iter().map(|e| e.id).collect::<Vec<i32>>(),
+        vec![3, 4]
+    );
+
+    let locked = TestEntity::query()
+        .filter("id = $1", &[&1])
+        .for_share()
+        .nowait()
+        .first(&second_worker)
+        .await;
+    assert!(locked.is_err());
+    second_worker.rollback().await.unwrap();
+
+    let shared = TestEntity::query()
+        .filter("id = $1", &[&5])
+        .for_share()
+        .first(&db)
+        .await
+        .unwrap();
+    assert!(shared.is_some());
+
+    first_worker.commit().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_queue() {
+    use crate::queue::{self, Backoff, Job, JobHandler, JobState, Worker};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    struct Recorder(Arc<Mutex<Vec<String>>>);
+
+    #[async_trait]
+    impl JobHandler for Recorder {
+        async fn handle(&self, db: &DB, job: &Job) -> Result<(), String> {
+            if job.payload.starts_with("stolen") {
+                // claimed again by another worker after the lock timeout
+                let query = "UPDATE oxidizer_jobs SET attempts = attempts + 1 WHERE id = $1";
+                db.execute(query, &[&job.id]).await.unwrap();
+            }
+            if job.payload.ends_with("fail") {
+                return Err(format!("attempt {}", job.attempts));
+            }
+            self.0.lock().unwrap().push(job.payload.clone());
+            Ok(())
+        }
+    }
+
+    let db = super::db::test_utils::create_test_db("test_queue").await;
+
+    db.migrate_tables(&[Job::create_migration().unwrap()])
+        .await
+        .unwrap();
+
+    let done = Arc::new(Mutex::new(Vec::new()));
+    let worker = Worker::new(&db, Recorder(done.clone()))
+        .max_attempts(2)
+        .backoff(Backoff {
+            base: Duration::from_secs(0),
+            max: Duration::from_secs(0),
+        });
+
+    queue::enqueue(&db, "first", Utc::now()).await.unwrap();
+    queue::enqueue(&db, "fail", Utc::now()).await.unwrap();
+    let later = queue::enqueue(&db, "later", Utc::now() + chrono::Duration::hours(1))
+        .await
+        .unwrap();
+
+    assert_eq!(2, worker.run_once().await.unwrap());
+    assert_eq!(vec!["first".to_string()], *done.lock().unwrap());
+
+    let failed = queue::Job::query()
+        .filter("payload = $1", &[&"fail"])
+        .first(&db)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(JobState::Pending, failed.state);
+    assert_eq!(1, failed.attempts);
+    assert_eq!(Some("attempt 1".to_string()), failed.last_error);
+
+    assert_eq!(1, worker.run_once().await.unwrap());
+    assert_eq!(0, worker.run_once().await.unwrap());
+
+    let dead = queue::dead_jobs(&db, queue::DEFAULT_QUEUE).await.unwrap();
+    assert_eq!(1, dead.len());
+    assert_eq!(2, dead[0].attempts);
+    assert_eq!(Some("attempt 2".to_string()), dead[0].last_error);
+
+    assert!(queue::retry(&db, dead[0].id).await.unwrap());
+    assert!(!queue::retry(&db, later.id).await.unwrap());
+    assert_eq!(1, worker.run_once().await.unwrap());
+    let retried = Job::find_by_pk(&db, &dead[0].id).await.unwrap().unwrap();
+    assert_eq!(JobState::Pending, retried.state);
+    assert_eq!(1, retried.attempts);
+    Job::delete_where(&db, "id = $1", &[&retried.id])
+        .await
+        .unwrap();
+
+    // a stale worker neither completes nor fails a job claimed again
+    queue::enqueue(&db, "stolen", Utc::now()).await.unwrap();
+    queue::enqueue(&db, "stolen fail", Utc::now())
+        .await
+        .unwrap();
+    assert_eq!(2, worker.run_once().await.unwrap());
+    let stolen = Job::query()
+        .filter("payload LIKE $1", &[&"stolen%"])
+        .find(&db)
+        .await
+        .unwrap();
+    assert_eq!(2, stolen.len());
+    assert!(stolen.iter().all(|job| job.state == JobState::Running));
+    assert!(stolen.iter().all(|job| job.last_error.is_none()));
+    Job::delete_where(&db, "true", &[]).await.unwrap();
+    done.lock().unwrap().clear();
+
+    // an abandoned job which used its last attempt is not run again
+    let abandoned = queue::enqueue(&db, "abandoned", Utc::now()).await.unwrap();
+    db.execute(
+        "UPDATE oxidizer_jobs SET state = 'running', attempts = 2, locked_at = now() - interval '1 hour' WHERE id = $1",
+        &[&abandoned.id],
+    )
+    .await
+    .unwrap();
+    assert_eq!(0, worker.run_once().await.unwrap());
+    assert!(done.lock().unwrap().is_empty());
+    let dead = queue::dead_jobs(&db, queue::DEFAULT_QUEUE).await.unwrap();
+    assert_eq!(1, dead.len());
+    assert_eq!(abandoned.id, dead[0].id);
+    assert!(dead[0].last_error.is_some());
+    Job::delete_where(&db, "true", &[]).await.unwrap();
+
+    // an error completing a job does not strand the rest of the batch
+    db.execute(
+        "CREATE FUNCTION reject_delete() RETURNS trigger AS $$ BEGIN RAISE EXCEPTION 'rejected'; END; $$ LANGUAGE plpgsql",
+        &[],
+    )
+    .await
+    .unwrap();
+    db.execute(
+        "CREATE TRIGGER reject_delete BEFORE DELETE ON oxidizer_jobs FOR EACH ROW WHEN (OLD.payload = 'undeletable') EXECUTE PROCEDURE reject_delete()",
+        &[],
+    )
+    .await
+    .unwrap();
+    queue::enqueue(&db, "undeletable", Utc::now())
+        .await
+        .unwrap();
+    queue::enqueue(&db, "next", Utc::now()).await.unwrap();
+    assert!(worker.run_once().await.is_err());
+    assert_eq!(
+        vec!["undeletable".to_string(), "next".to_string()],
+        *done.lock().unwrap()
+    );
+    let remaining = Job::query().find(&db).await.unwrap();
+    assert_eq!(1, remaining.len());
+    assert_eq!("undeletable", remaining[0].payload);
+    db.execute("DROP TRIGGER reject_delete ON oxidizer_jobs", &[])
+        .await
+        .unwrap();
+    Job::delete_where(&db, "true", &[]).await.unwrap();
+    done.lock().unwrap().clear();
+
+    // an idle worker is woken by the notification of enqueue and keeps running through errors
+    let (stop, stopped) = tokio::sync::oneshot::channel::<()>();
+    let listening = Worker::new(&db, Recorder(done.clone())).poll_interval(Duration::from_secs(60));
+    let running = tokio::spawn(async move {
+        listening
+            .run_until(async {
+                let _ = stopped.await;
+            })
+            .await
+    });
+    tokio::time::delay_for(Duration::from_millis(500)).await;
+
+    db.execute(
+        "ALTER TABLE oxidizer_jobs RENAME TO oxidizer_jobs_moved",
+        &[],
+    )
+    .await
+    .unwrap();
+    db.notify(queue::CHANNEL, queue::DEFAULT_QUEUE)
+        .await
+        .unwrap();
+    tokio::time::delay_for(Duration::from_millis(500)).await;
+    db.execute(
+        "ALTER TABLE oxidizer_jobs_moved RENAME TO oxidizer_jobs",
+        &[],
+    )
+    .await
+    .unwrap();
+
+    queue::enqueue(&db, "notified", Utc::now()).await.unwrap();
+    for _ in 0..50 {
+        if done.lock().unwrap().len() == 1 {
+            break;
+        }
+        tokio::time::delay_for(Duration::from_millis(100)).await;
+    }
+    assert_eq!(vec!["notified".to_string()], *done.lock().unwrap());
+
+    stop.send(()).unwrap();
+    tokio::time::timeout(Duration::from_secs(5), running)
+        .await
+        .unwrap()
+        .unwrap();
+}
+
+#[tokio::test]
+async fn test_temporal_reserved_table_name() {
+    let db = super::db::test_utils::create_test_db("test_temporal_reserved_table_name").await;
+
+    db.migrate_tables(&[Table::create_migration().unwrap()])
+        .await
+        .unwrap();
+
+    let mut table = Table {
+        seats: 4,
+        ..Default::default()
+    };
+    table.save(&db).await.unwrap();
+    let rows = db.query("SELECT now()", &[]).await.unwrap();
+    let before_change: DateTime<Utc> = rows[0].get(0);
+    table.seats = 6;
+    table.save(&db).await.unwrap();
+
+    let found = Table::find_by_pk_as_of(&db, &table.id, before_change)
+        .await
+        .unwrap();
+    assert_eq!(4, found.unwrap().seats);
+    assert_eq!(6, Table::as_of(&db, Utc::now()).await.unwrap()[0].seats);
+}
+
+#[tokio::test]
+async fn test_emit_changes() {
+    use crate::changes::Change;
+    use futures::TryStreamExt;
+
+    let db = super::db::test_utils::create_test_db("test_emit_changes").await;
+
+    db.migrate_tables(&[TestEmitChanges::create_migration().unwrap()])
+        .await
+        .unwrap();
+
+    let mut changes = TestEmitChanges::changes(&db).await.unwrap();
+
+    let mut entity = TestEmitChanges {
+        name: "first".to_string(),
+        ..Default::default()
+    };
+    entity.save(&db).await.unwrap();
+    entity.name = "renamed".to_string();
+    entity.save(&db).await.unwrap();
+
+    match changes.try_next().await.unwrap().unwrap() {
+        Change::Inserted { key, row } => {
+            assert_eq!(entity.id, key);
+            assert_eq!("first", row.unwrap().name);
+        }
+        change => panic!("unexpected {:?}", change),
+    }
+    match changes.try_next().await.unwrap().unwrap() {
+        Change::Updated { key, row } => {
+            assert_eq!(entity.id, key);
+            assert_eq!("renamed", row.unwrap().name);
+        }
+        change => panic!("unexpected {:?}", change),
+    }
+
+    // rows too large for a notification only carry their key
+    entity.notes = Some("x".repeat(10000));
+    entity.save(&db).await.unwrap();
+    let change = changes.try_next().await.unwrap().unwrap();
+    assert_eq!(entity.id, *change.key());
+    assert!(change.row().is_none());
+
+    entity.notes = None;
+    entity.save(&db).await.unwrap();
+    assert!(changes.try_next().await.unwrap().unwrap().row().is_some());
+
+    let id = entity.id;
+    entity.delete(&db).await.unwrap();
+    match changes.try_next().await.unwrap().unwrap() {
+        Change::Deleted { key, row } => {
+            assert_eq!(id, key);
+            assert_eq!("renamed", row.unwrap().name);
+        }
+        change => panic!("unexpected {:?}", change),
+    }
+}
+
+#[tokio::test]
+async fn test_emit_changes_reserved_table_name() {
+    use crate::changes::Change;
+    use futures::TryStreamExt;
+
+    let db = super::db::test_utils::create_test_db("test_emit_changes_reserved_table_name").await;
+
+    db.migrate_tables(&[Order::create_migration().unwrap()])
+        .await
+        .unwrap();
+
+    let mut changes = Order::changes(&db).await.unwrap();
+
+    let mut order = Order {
+        reference: "A-1".to_string(),
+        ..Default::default()
+    };
+    order.save(&db).await.unwrap();
+
+    match changes.try_next().await.unwrap().unwrap() {
+        Change::Inserted { key, row } => {
+            assert_eq!(order.id, key);
+            assert_eq!("A-1", row.unwrap().reference);
+        }
+        change => panic!("unexpected {:?}", change),
+    }
+}
+
+#[tokio::test]
+async fn test_outbox() {
+    use crate::outbox::{self, OutboxMessage, Relay, TestPublisher};
+
+    let db = super::db::test_utils::create_test_db("test_outbox").await;
+
+    db.migrate_tables(&[
+        TestEntity::create_migration().unwrap(),
+        OutboxMessage::create_migration().unwrap(),
+    ])
+    .await
+    .unwrap();
+
+    let relay = Relay::new(&db, TestPublisher::new()).batch_size(2);
+
+    let tx = db.transaction().await.unwrap();
+    let mut entity = TestEntity {
+        name: "rolled back".to_string(),
+        ..Default::default()
+    };
+    entity.save(&tx).await.unwrap();
+    outbox::publish(&tx, "entities", "rolled back")
+        .await
+        .unwrap();
+    tx.rollback().await.unwrap();
+    assert_eq!(0, relay.run_once().await.unwrap());
+
+    let tx = db.transaction().await.unwrap();
+    for payload in ["first", "second", "third"].iter() {
+        outbox::publish(&tx, "entities", payload).await.unwrap();
+    }
+    tx.commit().await.unwrap();
+
+    // the broker is down, the messages stay in the outbox
+    relay.publisher().set_failing(true);
+    assert_eq!(0, relay.run_once().await.unwrap());
+    let head = OutboxMessage::query()
+        .order_by("id")
+        .first(&db)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(1, head.attempts);
+    assert_eq!(Some("broker unavailable".to_string()), head.last_error);
+    assert_eq!(3, OutboxMessage::count(&db, "true", &[]).await.unwrap());
+
+    // a single relay publishes at a time
+    relay.publisher().set_failing(false);
+    let other = db.transaction().await.unwrap();
+    other
+        .query("SELECT pg_advisory_xact_lock($1)", &[&outbox::RELAY_LOCK])
+        .await
+        .unwrap();
+    assert_eq!(0, relay.run_once().await.unwrap());
+    other.rollback().await.unwrap();
+
+    assert_eq!(2, relay.run_once().await.unwrap());
+    assert_eq!(1, relay.run_once().await.unwrap());
+    assert_eq!(0, relay.run_once().await.unwrap());
+
+    // concurrent transactions are published in the order of their commits
+    let earlier = db.transaction().await.unwrap();
+    outbox::publish(&earlier, "entities", "written first")
+        .await
+        .unwrap();
+    let later = db.transaction().await.unwrap();
+    outbox::publish(&later, "entities", "committed first")
+        .await
+        .unwrap();
+    later.commit().await.unwrap();
+    assert_eq!(1, relay.run_once().await.unwrap());
+    earlier.commit().await.unwrap();
+    assert_eq!(1, relay.run_once().await.unwrap());
+
+    let published: Vec<String> = relay
+        .publisher()
+        .published()
+        .into_iter()
+        .map(|message| message.payload)
+        .collect();
+    assert_eq!(
+        vec![
+            "first",
+            "second",
+            "third",
+            "committed first",
+            "written first"
+        ],
+        published
+    );
+    assert_eq!(0, OutboxMessage::count(&db, "true", &[]).await.unwrap());
+}
+
+#[tokio::test]
+async fn test_audited() {
+    use crate::audit::{self, Operation};
+
+    let db = super::db::test_utils::create_test_db("test_audited").await;
+
+    db.migrate_tables(&[TestAudited::create_migration().unwrap()])
+        .await
+        .unwrap();
+
+    let opened_at = Utc::now();
+    let mut account = TestAudited {
+        name: "checking".to_string(),
+        balance: 10,
+        opened_at: Some(opened_at),
+        ..Default::default()
+    };
+    account.save(&db).await.unwrap();
+
+    assert!(audit::set_actor(&db, "alice").await.is_err());
+    let tx = db.transaction().await.unwrap();
+    audit::set_actor(&tx, "alice").await.unwrap();
+    account.balance = 110;
+    account.save(&tx).await.unwrap();
+    tx.commit().await.unwrap();
+
+    // the actor is local to its transaction
+    TestAudited::update_where(&db, "id = $1", "balance = 0", &[&account.id])
+        .await
+        .unwrap();
+
+    let history = account.history(&db).await.unwrap();
+    assert_eq!(3, history.len());
+
+    assert_eq!(Operation::Insert, history[0].operation);
+    assert!(history[0].before.is_none());
+    let after = history[0].after.as_ref().unwrap();
+    assert_eq!(10, after.balance);
+    assert_eq!(
+        opened_at.timestamp_nanos() / 1000,
+        after.opened_at.unwrap().timestamp_nanos() / 1000
+    );
+    assert_eq!(None, history[0].actor);
+
+    assert_eq!(Operation::Update, history[1].operation);
+    assert_eq!(10, history[1].before.as_ref().unwrap().balance);
+    assert_eq!(110, history[1].after.as_ref().unwrap().balance);
+    assert_eq!(Some("alice".to_string()), history[1].actor);
+
+    assert_eq!(0, history[2].after.as_ref().unwrap().balance);
+    assert_eq!(None, history[2].actor);
+
+    let id = account.id;
+    account.delete(&db).await.unwrap();
+    let history = audit::history::<TestAudited>(&db, &id).await.unwrap();
+    assert_eq!(4, history.len());
+    assert_eq!(Operation::Delete, history[3].operation);
+    assert_eq!("checking", history[3].before.as_ref().unwrap().name);
+    assert!(history[3].after.is_none());
+}
+
+#[tokio::test]
+async fn test_audited_reserved_table_name() {
+    use crate::audit::Operation;
+
+    let db = super::db::test_utils::create_test_db("test_audited_reserved_table_name").await;
+
+    db.migrate_tables(&[Group::create_migration().unwrap()])
+        .await
+        .unwrap();
+
+    let mut group = Group {
+        name: "admins".to_string(),
+        ..Default::default()
+    };
+    group.save(&db).await.unwrap();
+    group.name = "owners".to_string();
+    group.save(&db).await.unwrap();
+
+    let history = group.history(&db).await.unwrap();
+    assert_eq!(2, history.len());
+    assert_eq!(Operation::Insert, history[0].operation);
+    assert_eq!("admins", history[1].before.as_ref().unwrap().name);
+    assert_eq!("owners", history[1].after.as_ref().unwrap().name);
+}
+
+#[tokio::test]
+async fn test_temporal() {
+    let db = super::db::test_utils::create_test_db("test_temporal").await;
+
+    db.migrate_tables(&[TestTemporal::create_migration().unwrap()])
+        .await
+        .unwrap();
+
+    let now = || async {
+        let rows = db.query("SELECT now()", &[]).await.unwrap();
+        rows[0].get::<usize, DateTime<Utc>>(0)
+    };
+
+    let before_insert = now().await;
+    let mut first = TestTemporal {
+        address: "first street".to_string(),
+        ..Default::default()
+    };
+    first.save(&db).await.unwrap();
+    let mut second = TestTemporal {
+        address: "second street".to_string(),
+        ..Default::default()
+    };
+    second.save(&db).await.unwrap();
+
+    let before_changes = now().await;
+    first.address = "moved street".to_string();
+    first.save(&db).await.unwrap();
+    second.delete(&db).await.unwrap();
+    let after_changes = now().await;
+
+    assert!(TestTemporal::as_of(&db, before_insert)
+        .await
+        .unwrap()
+        .is_empty());
+
+    let rows = TestTemporal::as_of(&db, before_changes).await.unwrap();
+    assert_eq!(
+        vec!["first street", "second street"],
+        rows.iter()
+            .map(|r| r.address.as_str())
+            .collect::<Vec<&str>>()
+    );
+
+    let rows = TestTemporal::as_of(&db, after_changes).await.unwrap();
+    assert_eq!(1, rows.len());
+    assert_eq!("moved street", rows[0].address);
+
+    let found = TestTemporal::find_by_pk_as_of(&db, &first.id, before_changes)
+        .await
+        .unwrap();
+    assert_eq!("first street", found.unwrap().address);
+    let found = TestTemporal::find_by_pk_as_of(&db, &first.id, before_insert)
+        .await
+        .unwrap();
+    assert!(found.is_none());
+
+    // a transaction started before a concurrent change closes the version after it
+    let tx = db.transaction().await.unwrap();
+    tx.query("SELECT now()", &[]).await.unwrap();
+    tokio::time::delay_for(std::time::Duration::from_millis(10)).await;
+    first.address = "concurrent street".to_string();
+    first.save(&db).await.unwrap();
+    let between = now().await;
+    let query = "UPDATE test_temporal SET address = $1 WHERE id = $2";
+    tx.execute(query, &[&"late street", &first.id])
+        .await
+        .unwrap();
+    tx.commit().await.unwrap();
+
+    let query = "SELECT COUNT(*) FROM test_temporal_versions WHERE valid_to < valid_from";
+    let inverted = db.query(query, &[]).await.unwrap();
+    assert_eq!(0, inverted[0].get::<usize, i64>(0));
+    let found = TestTemporal::find_by_pk_as_of(&db, &first.id, between)
+        .await
+        .unwrap();
+    assert_eq!("concurrent street", found.unwrap().address);
+    let found = TestTemporal::find_by_pk_as_of(&db, &first.id, now().await)
+        .await
+        .unwrap();
+    assert_eq!("late street", found.unwrap().address);
+}
+
+#[tokio::test]
+async fn test_copy() {
+    use futures::TryStreamExt;
+
+    let db = super::db::test_utils::create_test_db("test_copy").await;
+
+    db.migrate_tables(&[
+        TestEntity::create_migration().unwrap(),
+        TestTracked::create_migration().unwrap(),
+        TestTimestamps::create_migration().unwrap(),
+    ])
+    .await
+    .unwrap();
+
+    let entities = (0..1000).map(|i| TestEntity {
+        name: format!("entity {}", i),
+        integer: i,
+        integer64: i as i64 * 2,
+        boolean: i % 2 == 0,
+        ..Default::default()
+    });
+    let copied = TestEntity::copy_in(&db, entities).await.unwrap();
+    assert_eq!(copied, 1000);
+    assert_eq!(TestEntity::count(&db, "true", &[]).await.unwrap(), 1000);
+
+    let loaded: Vec<TestEntity> = TestEntity::copy_out(&db)
+        .await
+        .unwrap()
+        .try_collect()
+        .await
+        .unwrap();
+    assert_eq!(loaded.len(), 1000);
+    let entity = loaded.iter().find(|e| e.integer == 500).unwrap();
+    assert_eq!(entity.name, "entity 500");
+    assert_eq!(entity.integer64, 1000);
+    assert!(entity.boolean);
+    assert!(entity.id > 0);
+
+    let tracked = vec![TestTracked {
+        name: "copied".to_string(),
+        my_enum: MyEnum::Item2,
+        ..Default::default()
+    }];
+    TestTracked::copy_in(&db, tracked).await.unwrap();
+    let loaded: Vec<TestTracked> = TestTracked::copy_out(&db)
+        .await
+        .unwrap()
+        .try_collect()
+        .await
+        .unwrap();
+    assert_eq!(loaded.len(), 1);
+    assert_eq!(loaded[0].my_enum, MyEnum::Item2);
+    assert!(loaded[0].get_dirty_fields().is_empty());
+
+    let stamped = vec![TestTimestamps {
+        name: "stamped".to_string(),
+        ..Default::default()
+    }];
+    TestTimestamps::copy_in(&db, stamped).await.unwrap();
+    let loaded = TestTimestamps::first(&db, "name = $1", &[&"stamped"])
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(loaded.created_at.is_some());
+}
+
 #[tokio::test]
 async fn test_entity_field_ignore() {
     let db = super::db::test_utils::create_test_db("test_entity_field_ignore").await;
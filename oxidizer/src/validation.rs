@@ -0,0 +1,85 @@
+//!
+//! # Validation
+//!
+//! Fields can be validated before the entity is written by adding `#[validate(...)]` attributes.
+//! The derive generates a `validate()` method that `save`, `insert` and `update` call before
+//! sending any query. It returns `Error::Validation` listing every failing field.
+//!
+//! Supported validators:
+//! - `length(min = 1, max = 255)`: number of characters of a string
+//! - `email`: the string looks like an email address
+//! - `range(min = 0, max = 100)`: inclusive bounds of a number, cast to the type of the field
+//! - `custom = "fn_name"`: calls `fn_name(&value)`, which returns `Result<(), String>`
+//!
+//! `Option` fields are only validated when they hold a value. Entities with `#[entity(hooks)]` run
+//! `before_save` first, so it can normalise the values that are then validated.
+//!
+//! ```
+//! use oxidizer::*;
+//!
+//! fn not_admin(name: &str) -> Result<(), String> {
+//!     match name {
+//!         "admin" => Err("reserved name".to_string()),
+//!         _ => Ok(()),
+//!     }
+//! }
+//!
+//! #[derive(Entity, Default)]
+//! pub struct Account {
+//!     #[primary_key(increments)]
+//!     id: i32,
+//!
+//!     #[validate(length(min = 1, max = 255), custom = "not_admin")]
+//!     name: String,
+//!
+//!     #[validate(email)]
+//!     email: Option<String>,
+//!
+//!     #[validate(range(min = 0))]
+//!     balance: i64,
+//! }
+//! ```
+//!
+
+use std::fmt;
+
+/// A field that failed validation
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+impl FieldError {
+    pub fn new(field: &str, message: &str) -> Self {
+        FieldError {
+            field: field.to_string(),
+            message: message.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for FieldError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.field, self.message)
+    }
+}
+
+/// Checks that `value` has the shape of an email address (`local@domain.tld`)
+pub fn is_email(value: &str) -> bool {
+    if value.chars().any(|c| c.is_whitespace()) {
+        return false;
+    }
+
+    let mut parts = value.split('@');
+    let (local, domain) = match (parts.next(), parts.next(), parts.next()) {
+        (Some(local), Some(domain), None) => (local, domain),
+        _ => return false,
+    };
+
+    !local.is_empty()
+        && domain.contains('.')
+        && !domain.starts_with('.')
+        && !domain.ends_with('.')
+        && !domain.contains("..")
+}